@@ -0,0 +1,113 @@
+// Vectorised filter operations against their scalar versions, on the planes
+// of the rule110 automaton at its hot point. Run with
+//
+//     cargo bench --bench filters
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use powerpointrs::{
+    experiments::{self, RULE110_CLOCK},
+    filters::{scalar, DoubleFilter, Filter, Word},
+    runners::{minimal, Config, Halt, Runner},
+};
+
+type Minimal = minimal::Presentation;
+
+fn time(name: &str, iters: usize, mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iters {
+        f();
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{name:<24} {:>10.1} ns/iter",
+        elapsed.as_nanos() as f64 / iters as f64
+    );
+    elapsed
+}
+
+fn main() {
+    let config = Config {
+        target: RULE110_CLOCK,
+        ..Config::default()
+    };
+    let mut minimal = Minimal::build(experiments::rule110(), config).unwrap();
+    // part of the way through the show, so that the topmost shape is not at
+    // the very end of the filters
+    for _ in 0..1000 {
+        minimal.click(RULE110_CLOCK.0, RULE110_CLOCK.1).unwrap();
+    }
+    let visible = minimal.state.visible.clone();
+    let plane = minimal.state.planes[0].clone();
+    println!("{} shapes, {} cells", minimal.len(), visible.cells.len());
+
+    let mut double = DoubleFilter::new(minimal.len());
+    for (pair, (a, b)) in double
+        .cells
+        .iter_mut()
+        .zip(visible.cells.iter().zip(&plane.cells))
+    {
+        *pair = (*a, *b);
+    }
+    assert_eq!(
+        visible.last_and(&plane),
+        scalar::last_and(&visible.cells, &plane.cells)
+    );
+    assert_eq!(double.last(), scalar::last_pair(&double.cells));
+
+    const ITERS: usize = 1_000_000;
+    time("last_and scalar", ITERS, || {
+        black_box(scalar::last_and(black_box(&visible.cells), &plane.cells));
+    });
+    time("last_and", ITERS, || {
+        black_box(black_box(&visible).last_and(&plane));
+    });
+    time("DoubleFilter::last scalar", ITERS, || {
+        black_box(scalar::last_pair(black_box(&double.cells)));
+    });
+    time("DoubleFilter::last", ITERS, || {
+        black_box(black_box(&double).last());
+    });
+
+    // worst case, only the bottom cell has shapes in both filters
+    let mut bottom = plane.clone();
+    bottom.cells[1..].fill(0);
+    time("last_and bottom scalar", ITERS, || {
+        black_box(scalar::last_and(black_box(&visible.cells), &bottom.cells));
+    });
+    time("last_and bottom", ITERS, || {
+        black_box(black_box(&visible).last_and(&bottom));
+    });
+
+    let mut cells = visible.clone();
+    let unset = Filter {
+        cells: plane.cells.iter().map(|cell| cell.rotate_left(7)).collect(),
+    };
+    time("apply scalar", ITERS, || {
+        scalar::apply(black_box(&mut cells.cells), &plane.cells, &unset.cells);
+    });
+    time("apply", ITERS, || {
+        black_box(&mut cells).apply(&plane, &unset);
+    });
+
+    // whole runs, the topmost search being one part of each click
+    run::<u32>(config);
+    run::<u64>(config);
+    run::<u128>(config);
+}
+
+fn run<W: Word>(config: Config) {
+    let mut minimal = minimal::Presentation::<W>::build(experiments::rule110(), config).unwrap();
+    let halts = [Halt::Topmost("STOP"), Halt::EndOfShow];
+    let start = Instant::now();
+    let run = minimal.run(RULE110_CLOCK, &halts, 100_000).unwrap();
+    println!(
+        "run of {} clicks, u{:<3} {:>10.1?}",
+        run.clicks,
+        W::SIZE,
+        start.elapsed()
+    );
+}
@@ -0,0 +1,165 @@
+// Runners against the minimal one on the rule110 automaton. Run with
+//
+//     cargo bench --bench runners
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use powerpointrs::{
+    experiments::{self, rule110_input, RULE110_CLOCK, RULE110_SETUP},
+    runners::{memo, minimal, sliced, Config, Halt, Runner},
+};
+
+type Minimal = minimal::Presentation;
+
+const STEPS: usize = 500;
+// Runs to the STOP shape timed together
+const RUNS: usize = 5;
+
+fn time<T>(name: &str, f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    println!("{name:<32} {elapsed:>10.1?}");
+    (result, elapsed)
+}
+
+fn main() {
+    let config = Config {
+        target: RULE110_CLOCK,
+        ..Config::default()
+    };
+    sliced_lanes(config);
+    memo_stop(config);
+    dense_clicks(config);
+}
+
+// Every input of the automaton at once on the lanes of the sliced runner,
+// against a minimal run per input
+fn sliced_lanes(config: Config) {
+    let (runs, minimal) = time("64 minimal runs", || {
+        (0..sliced::LANES)
+            .map(|lane| {
+                let mut minimal = Minimal::build(experiments::rule110(), config).unwrap();
+                for (x, y) in RULE110_SETUP.into_iter().chain(rule110_input(lane)) {
+                    minimal.click(x, y).unwrap();
+                }
+                for _ in 0..STEPS {
+                    minimal.click(RULE110_CLOCK.0, RULE110_CLOCK.1).unwrap();
+                }
+                minimal
+            })
+            .collect::<Vec<_>>()
+    });
+    let (sliced, lanes) = time("sliced run of 64 lanes", || {
+        let mut sliced = sliced::Presentation::build(experiments::rule110(), config).unwrap();
+        for (x, y) in RULE110_SETUP {
+            sliced.click(x, y, sliced::ALL);
+        }
+        for lane in 0..sliced::LANES {
+            for (x, y) in rule110_input(lane) {
+                sliced.click(x, y, 1 << lane);
+            }
+        }
+        for _ in 0..STEPS {
+            sliced.click(RULE110_CLOCK.0, RULE110_CLOCK.1, sliced::ALL);
+        }
+        sliced
+    });
+    for (lane, minimal) in runs.iter().enumerate() {
+        assert!((0..minimal.len()).all(|i| {
+            let (l, r) = (minimal.shape(i), sliced.shape(lane, i));
+            l.visible == r.visible && (!l.visible || l == r)
+        }));
+    }
+    println!(
+        "{:<32} {:>10.1}x",
+        "sliced speed-up",
+        minimal.as_secs_f64() / lanes.as_secs_f64()
+    );
+}
+
+// Runs to the STOP shape skipping through the recorded segments, against
+// minimal ones clicking through, then with the builds counted. A hit skips
+// at most span clicks, so the speed-up stays the same on longer runs
+fn memo_stop(config: Config) {
+    let halts = [Halt::Topmost("STOP"), Halt::EndOfShow];
+    let (mut minimals, minimal_builds) = time("5 minimal builds", || {
+        (0..RUNS)
+            .map(|_| Minimal::build(experiments::rule110(), config).unwrap())
+            .collect::<Vec<_>>()
+    });
+    let (mut memos, memo_builds) = time("5 memo builds", || {
+        (0..RUNS)
+            .map(|_| memo::Presentation::build(experiments::rule110(), config).unwrap())
+            .collect::<Vec<_>>()
+    });
+    let (runs, minimal) = time("5 minimal runs to STOP", || {
+        minimals
+            .iter_mut()
+            .map(|minimal| minimal.run(RULE110_CLOCK, &halts, 100_000).unwrap().clicks)
+            .collect::<Vec<_>>()
+    });
+    let (skips, skipped) = time("5 memo runs to STOP", || {
+        let halts = [Halt::Topmost("STOP"), Halt::EndOfShow];
+        memos
+            .iter_mut()
+            .map(|memo| memo.run(RULE110_CLOCK, &halts, 100_000).unwrap().clicks)
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(runs, skips);
+    for (minimal, memo) in minimals.iter().zip(&memos) {
+        assert_eq!(minimal.state_hash(), memo.inner.state_hash());
+    }
+    println!(
+        "{:<32} {:>10.1}x",
+        "memo speed-up",
+        minimal.as_secs_f64() / skipped.as_secs_f64()
+    );
+    println!(
+        "{:<32} {:>10.1}x",
+        "memo speed-up with builds",
+        (minimal_builds + minimal).as_secs_f64() / (memo_builds + skipped).as_secs_f64()
+    );
+}
+
+// Runs to the STOP shape applying the dense click groups filter wide, against
+// ones playing them cell by cell
+fn dense_clicks(config: Config) {
+    let halts = [Halt::Topmost("STOP"), Halt::EndOfShow];
+    let builds = || {
+        (0..RUNS)
+            .map(|_| Minimal::build(experiments::rule110(), config).unwrap())
+            .collect::<Vec<_>>()
+    };
+    let (mut masked, mut unmasked) = (builds(), builds());
+    for minimal in &mut unmasked {
+        let timeline = &mut Arc::get_mut(&mut minimal.program).unwrap().timeline;
+        for context in timeline
+            .contexts
+            .iter_mut()
+            .chain([&mut timeline.main_context])
+        {
+            context.masks.iter_mut().for_each(|masks| *masks = None);
+        }
+    }
+    let run = |minimals: &mut Vec<Minimal>| {
+        minimals
+            .iter_mut()
+            .map(|minimal| minimal.run(RULE110_CLOCK, &halts, 100_000).unwrap().clicks)
+            .collect::<Vec<_>>()
+    };
+    let (runs, cells) = time("5 runs cell by cell", || run(&mut unmasked));
+    let (masks, filters) = time("5 runs with masks", || run(&mut masked));
+    assert_eq!(runs, masks);
+    for (unmasked, masked) in unmasked.iter().zip(&masked) {
+        assert_eq!(unmasked.state_hash(), masked.state_hash());
+    }
+    println!(
+        "{:<32} {:>10.1}x",
+        "masks speed-up",
+        cells.as_secs_f64() / filters.as_secs_f64()
+    );
+}
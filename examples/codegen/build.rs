@@ -0,0 +1,23 @@
+// Compiles rule110 to a module at build time, src/main.rs includes it
+use std::{env, path::Path};
+
+use powerpointrs::{
+    codegen,
+    experiments::{self, RULE110_CLOCK},
+    runners::Config,
+};
+
+fn main() {
+    let config = Config {
+        target: RULE110_CLOCK,
+        ..Config::default()
+    };
+    let out_dir = env::var("OUT_DIR").unwrap();
+    codegen::write(
+        experiments::rule110(),
+        config,
+        Path::new(&out_dir).join("rule110.rs"),
+    )
+    .unwrap();
+    println!("cargo::rerun-if-changed=build.rs");
+}
@@ -0,0 +1,45 @@
+// The minimal runner against the module codegen made of rule110 at build
+// time, both running the automaton to its end. Run with
+//
+//     cargo run --release
+
+use std::time::Instant;
+
+use powerpointrs::{
+    experiments::{self, RULE110_CLOCK, RULE110_SETUP},
+    runners::{minimal, Config, Halt, Runner},
+};
+
+mod rule110 {
+    include!(concat!(env!("OUT_DIR"), "/rule110.rs"));
+}
+
+fn run<R: Runner>(name: &str) -> (usize, u64) {
+    let config = Config {
+        target: RULE110_CLOCK,
+        ..Config::default()
+    };
+    let mut presentation = R::build(experiments::rule110(), config).unwrap();
+    // input of the automaton, then the clicks on its hot point until it stops
+    let input = RULE110_SETUP
+        .into_iter()
+        .chain(experiments::rule110_input(1));
+    for (x, y) in input {
+        presentation.click(x, y).unwrap();
+    }
+    let halts = [Halt::Topmost("STOP"), Halt::EndOfShow];
+    let start = Instant::now();
+    let run = presentation.run(RULE110_CLOCK, &halts, 100_000).unwrap();
+    println!(
+        "{name:<10} {} clicks in {:>10.1?}",
+        run.clicks,
+        start.elapsed()
+    );
+    (run.clicks, presentation.state_hash())
+}
+
+fn main() {
+    let minimal = run::<minimal::Presentation>("minimal");
+    let generated = run::<rule110::Presentation>("generated");
+    assert_eq!(minimal, generated, "the runners disagree");
+}
@@ -1,40 +1,74 @@
-use std::time::Instant;
+use std::{error, time::Instant};
 
 use powerpointrs::{
-    experiments,
+    experiments::{self, RULE110_CLOCK, RULE110_SETUP},
+    lint::{self, Severity},
     runners::{
-        basic::Presentation as BasicPresentation, minimal::Presentation as MinimalPresentation,
+        basic::Presentation as BasicPresentation, memo::Presentation as MemoPresentation,
+        minimal::Presentation as MinimalPresentation,
+        reference::Presentation as ReferencePresentation, Config, Halt, Runner,
     },
-    Color,
+    Color, Error, Slide,
 };
 
-fn main() {
-    let slide = experiments::rule110();
-    let mut presentation = MinimalPresentation::from(slide, (0., 20.));
-    // let mut presentation = BasicPresentation::from(slide);
-    let start = Instant::now();
+fn run<R: Runner>(slide: Slide, clicks: &[(f32, f32)], profile: bool) -> Result<(), Error> {
+    for diagnostic in slide.validate(R::KIND) {
+        if diagnostic.severity == Severity::Error {
+            eprintln!("{diagnostic}");
+        }
+    }
 
-    let clicks = [
-        // trigger main
-        (15., 15.),
-        // set rule 110 (0b01101110)
-        (22., 4.),
-        (26., 4.),
-        (34., 4.),
-        (38., 4.),
-        (42., 4.),
-        // set initial state
-        (120., 10.),
-    ];
-    for (x, y) in clicks {
-        presentation.click(x, y);
+    let config = Config {
+        target: RULE110_CLOCK,
+        profile,
+        ..Config::default()
+    };
+    let mut presentation = R::build(slide, config)?;
+    let start = Instant::now();
+    for &(x, y) in clicks {
+        presentation.click(x, y)?;
     }
 
-    // presentation.update_filter(0., 20.);
-    for _ in 0..26500 {
-        presentation.click(0., 20.);
+    let halts = [Halt::Topmost("STOP"), Halt::EndOfShow];
+    let run = presentation.run(RULE110_CLOCK, &halts, 100_000)?;
+    match run.halt {
+        Some(0) => println!("stopped after {} clicks", run.clicks),
+        Some(_) => println!("end of show after {} clicks", run.clicks),
+        None => println!("no halt after {} clicks", run.clicks),
     }
     println!("{:?}", start.elapsed());
-    println!("{}", presentation.render(1., Color::WHITE).to_string());
-    println!("iterations: {}", presentation.iters);
+    println!("{}", presentation.render(1., Color::WHITE));
+    println!("iterations: {}", presentation.iters());
+    if profile {
+        println!("hottest triggers:");
+        for hot in presentation.hottest(20) {
+            println!("{hot}");
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn error::Error>> {
+    let slide = experiments::rule110();
+    // trigger main and set rule 110, then the initial state
+    let mut clicks = RULE110_SETUP.to_vec();
+    clicks.extend(experiments::rule110_input(1));
+    for diagnostic in lint::off_slide(&slide, &clicks) {
+        eprintln!("{diagnostic}");
+    }
+
+    let profile = std::env::args().nth(2).as_deref() == Some("profile");
+    match std::env::args().nth(1).as_deref() {
+        Some("basic") => run::<BasicPresentation>(slide, &clicks, profile)?,
+        Some("minimal") | None => run::<MinimalPresentation>(slide, &clicks, profile)?,
+        Some("reference") => run::<ReferencePresentation>(slide, &clicks, profile)?,
+        Some("memo") => run::<MemoPresentation>(slide, &clicks, profile)?,
+        Some(runner) => {
+            return Err(format!(
+                "unknown runner {runner:?}, expected basic, minimal, memo or reference"
+            )
+            .into())
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,177 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{Animation, Color, Effect, Referer, Shape, ShapeState, Slide, Z};
+
+static NEXT_SLIDE: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn next_slide_id() -> usize {
+    NEXT_SLIDE.fetch_add(1, Ordering::Relaxed)
+}
+
+// A shape of one particular slide. The slide is only checked at run time,
+// the type does not stop a handle from being given to another slide: its
+// timeline records the handle and building it fails with a foreign referer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Handle {
+    referer: Referer,
+}
+
+impl Handle {
+    pub fn referer(&self) -> Referer {
+        self.referer
+    }
+}
+
+pub struct ShapeBuilder<'a> {
+    slide: &'a mut Slide,
+    referer: Referer,
+}
+
+impl<'a> ShapeBuilder<'a> {
+    fn shape(&mut self) -> (&mut &'static str, &mut ShapeState) {
+        match &mut self.slide.shapes[self.referer.index()].1 {
+            Shape::Shape { name, state, .. } => (name, state),
+            Shape::Group { .. } => unreachable!("rects are built as shapes"),
+        }
+    }
+    pub fn z(self, z: Z) -> Self {
+        set_z(self.slide, self.referer, z);
+        self
+    }
+    pub fn color(mut self, color: impl Into<Color>) -> Self {
+        self.shape().1.color = color.into();
+        self
+    }
+    pub fn name(mut self, name: &'static str) -> Self {
+        *self.shape().0 = name;
+        self
+    }
+    pub fn handle(&self) -> Handle {
+        Handle {
+            referer: self.referer,
+        }
+    }
+}
+
+// Groups take their colors and names from their shapes, only their z can be
+// set
+pub struct GroupBuilder<'a> {
+    slide: &'a mut Slide,
+    referer: Referer,
+}
+
+impl<'a> GroupBuilder<'a> {
+    pub fn z(self, z: Z) -> Self {
+        set_z(self.slide, self.referer, z);
+        self
+    }
+    pub fn handle(&self) -> Handle {
+        Handle {
+            referer: self.referer,
+        }
+    }
+}
+
+fn set_z(slide: &mut Slide, referer: Referer, z: Z) {
+    match &mut slide.shapes[referer.index()].1 {
+        Shape::Shape { z: old, .. } | Shape::Group { z: old, .. } => *old = z,
+    }
+}
+
+impl From<ShapeBuilder<'_>> for Handle {
+    fn from(builder: ShapeBuilder<'_>) -> Self {
+        builder.handle()
+    }
+}
+
+impl From<GroupBuilder<'_>> for Handle {
+    fn from(builder: GroupBuilder<'_>) -> Self {
+        builder.handle()
+    }
+}
+
+pub struct Sequence<'a> {
+    slide: &'a mut Slide,
+    on: Option<Referer>,
+}
+
+impl<'a> Sequence<'a> {
+    pub fn animate(self, target: Handle, effect: impl Into<Effect>) -> AnimationBuilder<'a> {
+        self.slide
+            .tl_add(target.referer, effect.into(), false, self.on);
+        AnimationBuilder { sequence: self }
+    }
+    // Moves the target to the hot point of the given index
+    pub fn hot(self, target: Handle, index: usize) -> AnimationBuilder<'a> {
+        self.slide.tl_add_hot(target.referer, index, false, self.on);
+        AnimationBuilder { sequence: self }
+    }
+}
+
+pub struct AnimationBuilder<'a> {
+    sequence: Sequence<'a>,
+}
+
+impl<'a> AnimationBuilder<'a> {
+    fn animation(&mut self) -> &mut Animation {
+        let timeline = &mut self.sequence.slide.timeline;
+        let context = match self.sequence.on {
+            Some(referer) => &mut timeline.contexts[referer.index()],
+            None => &mut timeline.main_context,
+        };
+        context.animations.last_mut().unwrap()
+    }
+    // Start a new click group with this animation
+    pub fn after_click(mut self) -> Self {
+        self.animation().click = true;
+        self
+    }
+    pub fn animate(self, target: Handle, effect: impl Into<Effect>) -> AnimationBuilder<'a> {
+        self.sequence.animate(target, effect)
+    }
+    pub fn hot(self, target: Handle, index: usize) -> AnimationBuilder<'a> {
+        self.sequence.hot(target, index)
+    }
+}
+
+impl Slide {
+    pub fn rect(&mut self, x: f32, y: f32, w: f32, h: f32) -> ShapeBuilder<'_> {
+        let referer = self.add(Shape::Shape {
+            z: Z(0, 0, 0),
+            name: "",
+            state: ShapeState {
+                x,
+                y,
+                w,
+                h,
+                color: Color::BLACK,
+            },
+        });
+        ShapeBuilder {
+            slide: self,
+            referer,
+        }
+    }
+    pub fn group(&mut self, shapes: Vec<Shape>) -> GroupBuilder<'_> {
+        let referer = self.add(Shape::Group {
+            z: Z(0, 0, 0),
+            shapes,
+        });
+        GroupBuilder {
+            slide: self,
+            referer,
+        }
+    }
+    pub fn on(&mut self, trigger: Handle) -> Sequence<'_> {
+        Sequence {
+            slide: self,
+            on: Some(trigger.referer),
+        }
+    }
+    pub fn main(&mut self) -> Sequence<'_> {
+        Sequence {
+            slide: self,
+            on: None,
+        }
+    }
+}
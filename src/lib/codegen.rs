@@ -0,0 +1,444 @@
+// Compiles a slide to a Rust module playing it like the minimal runner, with
+// one function per context and click group and the cell masks inlined. The
+// module refers to this crate as ::powerpointrs, a build script can write it
+// to OUT_DIR and the crate include it:
+//
+//     codegen::write(experiments::rule110(), config, out_dir.join("rule110.rs"))?;
+//     mod rule110 { include!(concat!(env!("OUT_DIR"), "/rule110.rs")); }
+//
+// examples/codegen is such a crate, timing the module against the minimal
+// runner.
+
+use std::{fmt::Write, io, path::Path};
+
+use crate::{
+    runners::{
+        minimal::{BasicContext, Presentation},
+        Config,
+    },
+    Result, Slide,
+};
+
+// The generated cells are 64 bits whatever the word of the target
+type Cell = u64;
+
+fn cells(cells: &[Cell]) -> String {
+    let cells = cells.iter().map(|cell| format!("{cell:#x}"));
+    cells.collect::<Vec<_>>().join(", ")
+}
+
+// One item per line for the array literals
+fn join(items: impl Iterator<Item = String>) -> String {
+    items.map(|item| format!("    {item},\n")).collect()
+}
+
+// Assignments applying the operation to the cell, nothing if it is a no-op
+fn apply(out: &mut String, cell: &str, set: Cell, unset: Cell) {
+    match (set, unset) {
+        (0, 0) => {}
+        (set, 0) => writeln!(out, "    {cell} |= {set:#x};").unwrap(),
+        (0, unset) => writeln!(out, "    {cell} &= !{unset:#x};").unwrap(),
+        (set, unset) => writeln!(out, "    {cell} = ({cell} & !{unset:#x}) | {set:#x};").unwrap(),
+    }
+}
+
+// Click group functions of the context and the function dispatching on its
+// head, they return the number of animations of the slide they play
+fn context(out: &mut String, name: &str, context: &BasicContext<Cell>) {
+    let mut arms = String::new();
+    for (group, animations) in context.animations.iter().enumerate() {
+        let sources = context.sources[group];
+        let group = group + 1;
+        writeln!(arms, "        {group} => {name}_{group}(p),").unwrap();
+        writeln!(out, "fn {name}_{group}(p: &mut Presentation) -> usize {{").unwrap();
+        for animation in animations {
+            let cell = animation.cell;
+            let visibility = &animation.visibility;
+            apply(
+                out,
+                &format!("p.visible[{cell}]"),
+                visibility.set,
+                visibility.unset,
+            );
+            for (plane, targeting) in animation.targeting.iter().enumerate() {
+                apply(
+                    out,
+                    &format!("p.planes[{plane}][{cell}]"),
+                    targeting.set,
+                    targeting.unset,
+                );
+            }
+            for (shape, place) in &animation.moves {
+                writeln!(out, "    p.places[{shape}] = {place};").unwrap();
+            }
+        }
+        writeln!(out, "    {sources}\n}}\n").unwrap();
+    }
+    // an empty main sequence wraps around to head 0 and leaves the parameter unused
+    let p = if context.animations.is_empty() {
+        arms.push_str("        0 => 0,\n");
+        "_"
+    } else {
+        "p"
+    };
+    writeln!(
+        out,
+        "fn {name}({p}: &mut Presentation, head: usize) -> usize {{
+    match head {{
+{arms}        _ => unreachable!(),
+    }}
+}}
+"
+    )
+    .unwrap();
+}
+
+// Hash of what a generated module compiles in, stable across builds and
+// targets, the ids the slide hands out to its shapes left out
+pub fn fingerprint(slide: &Slide) -> u64 {
+    let mut text = format!(
+        "{:?} {:?} {:?}",
+        slide.width, slide.height, slide.hot_points
+    );
+    for (id, shape) in &slide.shapes {
+        write!(text, " {id} {shape:?}").unwrap();
+    }
+    let contexts = slide.timeline.contexts.iter();
+    for context in [&slide.timeline.main_context].into_iter().chain(contexts) {
+        text.push_str(" |");
+        for animation in &context.animations {
+            let (index, size) = animation.target.span();
+            write!(
+                text,
+                " {index} {size} {} {:?}",
+                animation.click, animation.effect
+            )
+            .unwrap();
+        }
+    }
+    // FNV-1a
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// Source of the module playing the slide, the hot points are those of the
+// slide and the config target
+pub fn generate(slide: Slide, config: Config) -> Result<String> {
+    let fingerprint = fingerprint(&slide);
+    let presentation = Presentation::<Cell>::try_from(slide, config.target)?;
+    let (program, state) = (&*presentation.program, &presentation.state);
+    let len = program.states_const.len();
+    let triggers = program
+        .timeline
+        .contexts
+        .iter()
+        .enumerate()
+        .filter(|(_, context)| !context.animations.is_empty())
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    let mut out = String::new();
+
+    let sizes = program
+        .states_const
+        .iter()
+        .map(|s| format!("({:?}, {:?})", s.w, s.h));
+    let colors = program.states_const.iter().map(|s| {
+        let color = s.color;
+        format!("Color::new({}, {}, {})", color.r, color.g, color.b)
+    });
+    let names = program.names.iter().map(|name| format!("{name:?}"));
+    let lattice = program.lattice.iter().map(|positions| {
+        let positions = positions.iter().map(|(x, y)| format!("({x:?}, {y:?})"));
+        format!("&[{}]", positions.collect::<Vec<_>>().join(", "))
+    });
+    let targets = program
+        .targets
+        .iter()
+        .map(|(x, y)| format!("({x:?}, {y:?})"));
+    let groups = program
+        .timeline
+        .contexts
+        .iter()
+        .chain([&program.timeline.main_context])
+        .map(|context| context.animations.len().to_string());
+    let planes = state
+        .planes
+        .iter()
+        .map(|plane| format!("vec![{}]", cells(&plane.cells)));
+    writeln!(
+        out,
+        "// Generated by powerpointrs::codegen, do not edit
+
+use ::powerpointrs::{{
+    codegen, hash, in_bounds,
+    render::Canvas,
+    runners::{{Config, Outcome, Runner, RunnerKind, ShapeView, ShowEnd, Trigger}},
+    Color, Error, Result, Slide,
+}};
+
+type Cell = u64;
+const CELL_SIZE: usize = 64;
+// codegen::fingerprint of the slide compiled in
+const SLIDE: u64 = {fingerprint:#x};
+
+pub const LEN: usize = {len};
+const WIDTH: f32 = {width:?};
+const HEIGHT: f32 = {height:?};
+static SIZES: [(f32, f32); LEN] = [
+{sizes}];
+static COLORS: [Color; LEN] = [
+{colors}];
+static NAMES: [&str; LEN] = [
+{names}];
+static LATTICE: [&[(f32, f32)]; LEN] = [
+{lattice}];
+static TARGETS: [(f32, f32); {targets_len}] = [
+{targets}];
+// click groups of each shape context, then of the main one
+static GROUPS: [usize; LEN + 1] = [
+{groups}];
+
+#[derive(Clone)]
+pub struct Presentation {{
+    visible: Vec<Cell>,
+    planes: Vec<Vec<Cell>>,
+    places: Vec<u32>,
+    heads: Vec<usize>,
+    iters: usize,
+    show_end: ShowEnd,
+}}
+
+impl Default for Presentation {{
+    fn default() -> Self {{
+        Self {{
+            visible: vec![{visible}],
+            planes: vec![{planes}],
+            places: vec![0; LEN],
+            heads: vec![0; LEN + 1],
+            iters: 0,
+            show_end: ShowEnd::default(),
+        }}
+    }}
+}}
+
+fn covers(index: usize, place: u32, x: f32, y: f32) -> bool {{
+    let (sx, sy) = LATTICE[index][place as usize];
+    let (w, h) = SIZES[index];
+    x >= sx && y >= sy && x <= sx + w && y <= sy + h
+}}
+
+fn last_and(a: &[Cell], b: &[Cell]) -> Option<usize> {{
+    for (index, (a, b)) in a.iter().zip(b).enumerate().rev() {{
+        let combined = a & b;
+        if combined != 0 {{
+            return Some(index * CELL_SIZE + CELL_SIZE - 1 - combined.leading_zeros() as usize);
+        }}
+    }}
+    None
+}}
+",
+        width = program.width,
+        height = program.height,
+        sizes = join(sizes),
+        colors = join(colors),
+        names = join(names),
+        lattice = join(lattice),
+        targets_len = program.targets.len(),
+        targets = join(targets),
+        groups = join(groups),
+        visible = cells(&state.visible.cells),
+        planes = planes.collect::<Vec<_>>().join(", "),
+    )
+    .unwrap();
+
+    context(&mut out, "main_context", &program.timeline.main_context);
+    for &index in &triggers {
+        let name = format!("context_{index}");
+        context(&mut out, &name, &program.timeline.contexts[index]);
+    }
+
+    let (trigger, dispatch) = if triggers.is_empty() {
+        (
+            "self.under(x, y);\n        let trigger = None;".to_string(),
+            "main_context(self, head)".to_string(),
+        )
+    } else {
+        // runs of consecutive triggers as ranges
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        for &index in &triggers {
+            match runs.last_mut() {
+                Some((_, end)) if *end + 1 == index => *end = index,
+                _ => runs.push((index, index)),
+            }
+        }
+        let indices = runs.iter().map(|&(start, end)| match end - start {
+            0 => start.to_string(),
+            1 => format!("{start} | {end}"),
+            _ => format!("{start}..={end}"),
+        });
+        let arms = triggers
+            .iter()
+            .map(|index| format!("            Some({index}) => context_{index}(self, head),\n"));
+        (
+            format!(
+                "let trigger = self\n            .under(x, y)\n            .filter(|index| matches!(index, {}));",
+                indices.collect::<Vec<_>>().join(" | ")
+            ),
+            format!(
+                "match trigger {{\n{}            _ => main_context(self, head),\n        }}",
+                arms.collect::<String>()
+            ),
+        )
+    };
+    writeln!(
+        out,
+        "impl Presentation {{
+    pub fn under(&mut self, x: f32, y: f32) -> Option<usize> {{
+        if !in_bounds(x, y, WIDTH, HEIGHT) {{
+            return None;
+        }}
+        if let Some(plane) = TARGETS.iter().position(|target| *target == (x, y)) {{
+            let last = last_and(&self.visible, &self.planes[plane]);
+            let cells = self.visible.len();
+            self.iters += last.map_or(cells, |index| cells - index / CELL_SIZE);
+            return last;
+        }}
+        for i in (0..LEN).rev() {{
+            if self.visible[i / CELL_SIZE] & (1 << (i % CELL_SIZE)) != 0 && covers(i, self.places[i], x, y) {{
+                self.iters += LEN - i;
+                return Some(i);
+            }}
+        }}
+        self.iters += LEN;
+        None
+    }}
+
+    pub fn click(&mut self, x: f32, y: f32) -> Result<Outcome> {{
+        if !in_bounds(x, y, WIDTH, HEIGHT) {{
+            return Ok(Outcome::Missed);
+        }}
+        {trigger}
+        let index = trigger.unwrap_or(LEN);
+        let head = if self.heads[index] == GROUPS[index] {{
+            if trigger.is_none() && self.show_end == ShowEnd::Stop {{
+                return Ok(Outcome::EndOfShow);
+            }}
+            usize::from(GROUPS[index] != 0)
+        }} else {{
+            self.heads[index] + 1
+        }};
+        self.heads[index] = head;
+        let animations = {dispatch};
+        Ok(Outcome::Fired {{
+            trigger: Trigger::from(trigger),
+            animations,
+        }})
+    }}
+}}
+
+// The slide is compiled in, build only checks it was given the same one and
+// reads the show end of the config
+impl Runner for Presentation {{
+    const KIND: RunnerKind = RunnerKind::Minimal;
+    type Snapshot = (Vec<Cell>, Vec<u32>, Vec<usize>);
+
+    fn build(slide: Slide, config: Config) -> Result<Self> {{
+        let found = codegen::fingerprint(&slide);
+        if found != SLIDE {{
+            return Err(Error::SlideMismatch {{
+                expected: SLIDE,
+                found,
+            }});
+        }}
+        Ok(Self {{
+            show_end: config.show_end,
+            ..Self::default()
+        }})
+    }}
+    fn click(&mut self, x: f32, y: f32) -> Result<Outcome> {{
+        Presentation::click(self, x, y)
+    }}
+    fn under(&mut self, x: f32, y: f32) -> Option<usize> {{
+        Presentation::under(self, x, y)
+    }}
+    fn render(&self, scale: f32, background: Color) -> Canvas<Color> {{
+        let width = (WIDTH * scale) as usize;
+        let height = (HEIGHT * scale) as usize;
+        let mut canvas = Canvas::new(width, height, background);
+        for i in 0..LEN {{
+            let shape = self.shape(i);
+            if shape.visible {{
+                let x = (shape.x * scale + 0.5) as isize;
+                let y = (shape.y * scale + 0.5) as isize;
+                let w = (shape.w * scale + 0.5) as isize;
+                let h = (shape.h * scale + 0.5) as isize;
+                canvas.fill_rect(x, y, w, h, shape.color);
+            }}
+        }}
+        canvas
+    }}
+    fn iters(&self) -> usize {{
+        self.iters
+    }}
+    fn set_iters(&mut self, iters: usize) {{
+        self.iters = iters;
+    }}
+    fn len(&self) -> usize {{
+        LEN
+    }}
+    fn shape(&self, index: usize) -> ShapeView {{
+        let (x, y) = LATTICE[index][self.places[index] as usize];
+        let (w, h) = SIZES[index];
+        ShapeView {{
+            visible: self.visible[index / CELL_SIZE] & (1 << (index % CELL_SIZE)) != 0,
+            x,
+            y,
+            w,
+            h,
+            color: COLORS[index],
+        }}
+    }}
+    fn name(&self, index: usize) -> &'static str {{
+        NAMES[index]
+    }}
+    // hashed from scratch like the minimal runner does incrementally
+    fn state_hash(&self) -> u64 {{
+        let cells = self.visible.iter().enumerate();
+        let places = self.places.iter().enumerate();
+        let heads = self.heads.iter().enumerate();
+        cells.fold(0, |hash, (i, cell)| hash ^ hash::cell(hash::VISIBLE, i, *cell))
+            ^ places.fold(0, |hash, (i, place)| hash ^ hash::key(hash::PLACE, i, *place as u64))
+            ^ heads.fold(0, |hash, (i, head)| hash ^ hash::key(hash::HEAD, i, *head as u64))
+    }}
+    fn snapshot(&self) -> Self::Snapshot {{
+        (self.visible.clone(), self.places.clone(), self.heads.clone())
+    }}
+}}"
+    )
+    .unwrap();
+    Ok(out)
+}
+
+// Writes the generated module to the file, for build scripts
+pub fn write(slide: Slide, config: Config, path: impl AsRef<Path>) -> io::Result<()> {
+    let source = generate(slide, config).map_err(io::Error::other)?;
+    std::fs::write(path, source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiments;
+
+    // The module checked in for the integration tests is what the shuttle
+    // compiles to
+    #[test]
+    fn shuttle() {
+        let source = generate(experiments::shuttle(), Config::default()).unwrap();
+        assert!(
+            source == include_str!("../../tests/generated/shuttle.rs"),
+            "tests/generated/shuttle.rs is stale, regenerate it with codegen::write"
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt;
+
+use crate::{
+    runners::{Config, Outcome, Runner, ShapeView, Trigger},
+    Color, Result, Slide,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mismatch {
+    Outcome {
+        left: Result<Outcome>,
+        right: Result<Outcome>,
+    },
+    Shape {
+        index: usize,
+        left: ShapeView,
+        right: ShapeView,
+    },
+    Pixel {
+        x: usize,
+        y: usize,
+        left: Color,
+        right: Color,
+    },
+}
+
+// Animation of the original slide, the trigger is a slide shape id
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Culprit {
+    pub trigger: Option<usize>,
+    pub animation: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    // number of clicks played before the divergence showed, 0 for the initial state
+    pub click: usize,
+    pub point: Option<(f32, f32)>,
+    pub mismatch: Mismatch,
+    pub shape: Option<(usize, &'static str)>,
+    pub culprits: Vec<Culprit>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.point {
+            Some((x, y)) => f.write_fmt(format_args!(
+                "runners diverge after click {} at ({x}, {y}): ",
+                self.click
+            ))?,
+            None => f.write_str("runners diverge on the initial state: ")?,
+        }
+        match &self.mismatch {
+            Mismatch::Outcome { left, right } => {
+                f.write_fmt(format_args!("outcome {left:?} != {right:?}"))?
+            }
+            Mismatch::Shape { index, left, right } => {
+                f.write_fmt(format_args!("shape {index} {left:?} != {right:?}"))?
+            }
+            Mismatch::Pixel { x, y, left, right } => {
+                f.write_fmt(format_args!("pixel ({x}, {y}) {left:?} != {right:?}"))?
+            }
+        }
+        if let Some((id, name)) = self.shape {
+            f.write_fmt(format_args!(", slide shape {id} {name:?}"))?;
+        }
+        for culprit in &self.culprits {
+            match culprit.trigger {
+                Some(trigger) => f.write_fmt(format_args!(
+                    ", animation {} triggered by shape {trigger}",
+                    culprit.animation
+                ))?,
+                None => f.write_fmt(format_args!(
+                    ", animation {} of the main sequence",
+                    culprit.animation
+                ))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Slide shape id of every flattened shape, in the order the runners lay them out
+pub fn flatten(slide: &Slide) -> Vec<usize> {
+    let mut ids = (0..slide.shapes.len()).collect::<Vec<_>>();
+    ids.sort_by_key(|id| slide.shapes[*id].1.z());
+    ids.into_iter()
+        .rev()
+        .flat_map(|id| std::iter::repeat_n(id, slide.shapes[id].1.size()))
+        .collect()
+}
+
+// Follows which click group of each context fires, with the basic runner semantics
+struct Tracker {
+    // click groups of each context as ranges of animation indices
+    groups: Vec<Vec<(usize, usize)>>,
+    heads: Vec<usize>,
+}
+
+impl Tracker {
+    fn new(slide: &Slide) -> Self {
+        let groups = slide
+            .timeline
+            .contexts
+            .iter()
+            .chain([&slide.timeline.main_context])
+            .map(|context| {
+                let mut groups = Vec::new();
+                let mut start = 0;
+                for (index, animation) in context.animations.iter().enumerate().skip(1) {
+                    if animation.click {
+                        groups.push((start, index));
+                        start = index;
+                    }
+                }
+                if start < context.animations.len() {
+                    groups.push((start, context.animations.len()));
+                }
+                groups
+            })
+            .collect::<Vec<_>>();
+        let heads = vec![0; groups.len()];
+        Self { groups, heads }
+    }
+    // Returns the animation range of the group fired by the context
+    fn fire(&mut self, context: usize) -> (usize, usize) {
+        let groups = &self.groups[context];
+        if groups.is_empty() {
+            return (0, 0);
+        }
+        let head = &mut self.heads[context];
+        if *head >= groups.len() {
+            *head = 0;
+        }
+        *head += 1;
+        groups[*head - 1]
+    }
+}
+
+fn first_mismatch<A: Runner, B: Runner>(left: &A, right: &B) -> Option<Mismatch> {
+    for index in 0..left.len().max(right.len()) {
+        if index >= left.len() || index >= right.len() {
+            let view = if index < left.len() {
+                left.shape(index)
+            } else {
+                right.shape(index)
+            };
+            return Some(Mismatch::Shape {
+                index,
+                left: view,
+                right: view,
+            });
+        }
+        let (l, r) = (left.shape(index), right.shape(index));
+        // positions of hidden shapes are not observable
+        let differ = l.visible != r.visible || (l.visible && l != r);
+        if differ {
+            return Some(Mismatch::Shape {
+                index,
+                left: l,
+                right: r,
+            });
+        }
+    }
+    let (l, r) = (
+        left.render(1., Color::WHITE),
+        right.render(1., Color::WHITE),
+    );
+    let index = l.pixels.iter().zip(&r.pixels).position(|(a, b)| a != b)?;
+    Some(Mismatch::Pixel {
+        x: index % l.width,
+        y: index / l.width,
+        left: l.pixels[index],
+        right: r.pixels[index],
+    })
+}
+
+// Plays the script on both runners and stops at the first click after which
+// their outcomes, visible shapes or renders disagree
+pub fn compare<A: Runner, B: Runner>(
+    slide: &Slide,
+    config: Config,
+    script: &[(f32, f32)],
+) -> Result<Option<Divergence>> {
+    let flat = flatten(slide);
+    let mut tracker = Tracker::new(slide);
+    let main = slide.timeline.contexts.len();
+    let mut left = A::build(slide.clone(), config)?;
+    let mut right = B::build(slide.clone(), config)?;
+    let mut mismatch = first_mismatch(&left, &right);
+    let mut click = 0;
+    let mut point = None;
+    let mut trigger = None;
+    let mut range = (0, 0);
+    for &(x, y) in script {
+        if mismatch.is_some() {
+            break;
+        }
+        click += 1;
+        point = Some((x, y));
+        let (l, r) = (left.click(x, y), right.click(x, y));
+        (trigger, range) = match l {
+            Ok(Outcome::Fired {
+                trigger: Trigger::Shape(index),
+                ..
+            }) => (Some(flat[index]), tracker.fire(flat[index])),
+            Ok(Outcome::Fired { .. }) => (None, tracker.fire(main)),
+            _ => (None, (0, 0)),
+        };
+        mismatch = if l != r {
+            Some(Mismatch::Outcome { left: l, right: r })
+        } else if l == Ok(Outcome::EndOfShow) {
+            break;
+        } else {
+            first_mismatch(&left, &right)
+        };
+    }
+    Ok(mismatch.map(|mismatch| {
+        let shape = match &mismatch {
+            Mismatch::Shape { index, .. } => flat.get(*index).copied(),
+            _ => None,
+        };
+        let context = match trigger {
+            Some(id) => &slide.timeline.contexts[id],
+            None => &slide.timeline.main_context,
+        };
+        let culprits = (range.0..range.1)
+            .filter(|animation| Some(context.animations[*animation].target.index()) == shape)
+            .map(|animation| Culprit { trigger, animation })
+            .collect();
+        Divergence {
+            click,
+            point,
+            mismatch,
+            shape: shape.map(|id| (id, slide.shapes[id].1.name())),
+            culprits,
+        }
+    }))
+}
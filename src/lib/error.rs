@@ -0,0 +1,46 @@
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    // the runner has no semantics for this effect
+    UnsupportedEffect { effect: &'static str, shape: usize },
+    // the runner has no semantics for this kind of shape
+    UnsupportedShape { shape: usize },
+    // an animation or trigger refers to a shape the slide doesn't have
+    DanglingReferer { referer: usize, shapes: usize },
+    // an animation or trigger refers to a shape of another slide
+    ForeignReferer { referer: usize },
+    // a move to a hot point the slide never registered
+    UnknownHotPoint { index: usize, hot_points: usize },
+    // a generated runner was given another slide than the one compiled in
+    SlideMismatch { expected: u64, found: u64 },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnsupportedEffect { effect, shape } => {
+                f.write_fmt(format_args!("unsupported effect {effect} on shape {shape}"))
+            }
+            Error::UnsupportedShape { shape } => {
+                f.write_fmt(format_args!("unsupported shape {shape}"))
+            }
+            Error::DanglingReferer { referer, shapes } => f.write_fmt(format_args!(
+                "referer {referer} points outside of the slide ({shapes} shapes)"
+            )),
+            Error::ForeignReferer { referer } => {
+                f.write_fmt(format_args!("referer {referer} belongs to another slide"))
+            }
+            Error::UnknownHotPoint { index, hot_points } => f.write_fmt(format_args!(
+                "hot point {index} is not registered ({hot_points} hot points)"
+            )),
+            Error::SlideMismatch { expected, found } => f.write_fmt(format_args!(
+                "slide {found:#x} is not the one compiled in ({expected:#x})"
+            )),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
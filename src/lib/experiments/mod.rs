@@ -1,7 +1,11 @@
+#![allow(clippy::needless_range_loop)]
+
 mod rule110;
+mod shuttle;
 mod sort;
 mod test;
 
-pub use rule110::rule110;
+pub use rule110::{rule110, rule110_input, RULE110_CLOCK, RULE110_SETUP};
+pub use shuttle::shuttle;
 pub use sort::sort;
 pub use test::test;
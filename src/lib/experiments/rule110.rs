@@ -34,9 +34,30 @@ const D: f32 = 0.;
 const TX: f32 = 0.;
 const TY: f32 = 20.;
 
+// Hot point clicked to step the automaton
+pub const RULE110_CLOCK: (f32, f32) = (TX, TY);
+// Clicks triggering the main sequence, then setting rule 110 (0b01101110)
+pub const RULE110_SETUP: [(f32, f32); 6] = [
+    (15., 15.),
+    (22., 4.),
+    (26., 4.),
+    (34., 4.),
+    (38., 4.),
+    (42., 4.),
+];
+
+// Clicks setting the initial row, a cell every 4 columns from the right for
+// each bit of row
+pub fn rule110_input(row: usize) -> impl Iterator<Item = (f32, f32)> {
+    (0..usize::BITS as usize)
+        .filter(move |bit| row & (1 << bit) != 0)
+        .map(|bit| (120. - 4. * bit as f32, 10.))
+}
+
 #[rustfmt::skip]
 pub fn rule110() -> Slide {
     let mut s = Slide::new(120., 90.);
+    let clock = s.hot(TX, TY);
     let matrix = (0..N_GROUP)
         .map(|y| y as f32)
         .map(|y| {
@@ -69,7 +90,7 @@ pub fn rule110() -> Slide {
         let o = call_out[index];
         anim!(@s, o => Appear, on=i);
         for target in &matrix[index] {
-            anim!(@s, *target => Target(TX, TY), on=i);
+            anim!(@s, *target => Hot(clock), on=i);
             anim!(@s, *target => SlideIn, on=o);
         }
     }
@@ -97,7 +118,7 @@ pub fn rule110() -> Slide {
             anim!(@s, c0 => Place, on=c1);
         }
         for c1 in call_in.iter().copied() {
-            anim!(@s, c0 => Target(TX, TY), on=c1);
+            anim!(@s, c0 => Hot(clock), on=c1);
         }
     }
     let ox = s.width - W_CELL;
@@ -136,11 +157,11 @@ pub fn rule110() -> Slide {
             if let Some(cell_next) = cell.next {
                 if x > 0 {
                     anim!(@s, cells[x-1][y+1].reset => Appear, on=cell_next);
-                    anim!(@s, cells[x-1][y+1].reset => Target(TX, TY), on=cell_next);
+                    anim!(@s, cells[x-1][y+1].reset => Hot(clock), on=cell_next);
                     anim!(@s, call_in[x%N_GROUP] => Appear, on=cell_next);
                 }
                 for target in [cell.main, cell_next] {
-                    anim!(@s, target => Target(TX, TY), on=last);
+                    anim!(@s, target => Hot(clock), on=last);
                 }
                 last = cell_next;
             }
@@ -148,9 +169,9 @@ pub fn rule110() -> Slide {
         let x = N_COLUMNS-1;
         if y < N_ROWS-1 {
             let tmp = shape!(@s,ox-(x as f32 +1.)*(W_CELL+D), oy+(y as f32)*(W_CELL+D), W_CELL, W_CELL, z=(1+N_ROWS-y), c=(255, 255, 0), n="UPDATE");
-            anim!(@s, tmp => Target(TX, TY), on=last);
+            anim!(@s, tmp => Hot(clock), on=last);
             anim!(@s, call_in[(x+1)%N_GROUP] => Appear, on=tmp);
-            anim!(@s, cells[x][y+1].reset => Target(TX, TY), on=tmp);
+            anim!(@s, cells[x][y+1].reset => Hot(clock), on=tmp);
             anim!(@s, cells[x][y+1].reset => Appear, on=tmp);
             anim!(@s, tmp => Place, c=true, on=tmp);
             last = tmp;
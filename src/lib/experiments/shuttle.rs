@@ -0,0 +1,18 @@
+use crate::{z, MacroEffect, Slide};
+
+// A token shuttled between two hot points, clicking the tick sends it to the
+// input one and clicking it there brings it back to the clock
+pub fn shuttle() -> Slide {
+    let mut slide = Slide::new(40., 30.);
+    let clock = slide.hot(0., 0.);
+    let input = slide.hot(20., 0.);
+    let tick = slide.rect(0., 0., 4., 4.).z(z!(1)).handle();
+    let token = slide.rect(10., 10., 4., 4.).z(z!(2)).handle();
+    slide.on(tick).hot(token, input);
+    slide
+        .on(token)
+        .hot(token, clock)
+        .animate(token, MacroEffect::Place)
+        .after_click();
+    slide
+}
@@ -1,9 +1,9 @@
-use crate::{anim, shape, Referer, Slide, Z};
+use crate::{anim, shape, Referer, Slide};
 
 fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
     let h = h % 360.0;
-    let s = s.max(0.0).min(100.0) / 100.0;
-    let l = l.max(0.0).min(100.0) / 100.0;
+    let s = s.clamp(0.0, 100.0) / 100.0;
+    let l = l.clamp(0.0, 100.0) / 100.0;
 
     let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
     let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
@@ -42,6 +42,7 @@ const TY: f32 = 10.;
 
 pub fn sort() -> Slide {
     let mut s = Slide::new(80., 60.);
+    let clock = s.hot(TX, TY);
     let ox = 10.;
     let oy = N as f32 + 1.;
     let mut values = [(Referer::Shape(0), Referer::Shape(0)); N];
@@ -77,8 +78,8 @@ pub fn sort() -> Slide {
             anim!(@s, get => Disappear, on=get);
             anim!(@s, values[j].0 => Disappear, on=set);
             anim!(@s, values[j].1 => Target(x, oy+W-0.75), on=set);
-            anim!(@s, set => Target(TX, TY), on=c);
-            anim!(@s, get => Target(TX, TY), on=c);
+            anim!(@s, set => Hot(clock), on=c);
+            anim!(@s, get => Hot(clock), on=c);
         }
         for (s0, _) in sg[i] {
             for (s1, g1) in sg[i] {
@@ -101,8 +102,8 @@ pub fn sort() -> Slide {
     for i in 0..(N - 1) {
         anim!(@s, shift => Appear, on=cc[i]);
         for j in 0..(N - i - 1) {
-            anim!(@s, cc[j] => Target(TX, TY), c=true, on=run);
-            anim!(@s, cc[j+1] => Target(TX, TY), on=run);
+            anim!(@s, cc[j] => Hot(clock), c=true, on=run);
+            anim!(@s, cc[j+1] => Hot(clock), on=run);
         }
     }
     anim!(@s, stop => Appear, c=true, on=run);
@@ -1,13 +1,24 @@
-use crate::{anim, shape, z, Shape, Slide, Z};
+use crate::{shape, z, Effect, MacroEffect, Shape, Slide};
 
 pub fn test() -> Slide {
     let mut slide = Slide::new(40., 30.);
-    let r0 = shape!(@slide, 1, 1, 1, 1, z=(1), c=(255, 0, 0));
-    let r1 = shape!(@slide, 2, 2, 2, 2, z=(2), c=(0, 255, 0));
-    let r2 = shape!(@slide, 3, 3, 3, 3, z=(3), c=(0, 0, 255));
-    let r3 = slide.add(Shape::Group {
-        z: z!(3),
-        shapes: vec![
+    let r0 = slide
+        .rect(1., 1., 1., 1.)
+        .z(z!(1))
+        .color((255, 0, 0))
+        .handle();
+    let r1 = slide
+        .rect(2., 2., 2., 2.)
+        .z(z!(2))
+        .color((0, 255, 0))
+        .handle();
+    let r2 = slide
+        .rect(3., 3., 3., 3.)
+        .z(z!(3))
+        .color((0, 0, 255))
+        .handle();
+    let r3 = slide
+        .group(vec![
             Shape::Group {
                 z: z!(7),
                 shapes: vec![
@@ -17,19 +28,23 @@ pub fn test() -> Slide {
             },
             shape! {6, 6, 6, 6, z=(6), c=(250, 0, 200)},
             shape! {5, 5, 5, 5, z=(5), c=(200, 0, 250)},
-        ],
-    });
-    println!("{r0:?} {r1:?} {r2:?}");
-    anim!(@slide, r0 => Appear);
-    anim!(@slide, r1 => Appear, on=r0);
-    anim!(@slide, r0 => Disappear, on=r0);
-    anim!(@slide, r2 => Target(1., 1.), on=r2);
-    anim!(@slide, r2 => Target(1., 5.), on=r2);
-    anim!(@slide, r2 => Place, on=r2);
-    anim!(@slide, r3 => Appear, on=r1);
-    anim!(@slide, r3 => Target(1., 1.), on=r3);
-    anim!(@slide, r3 => Disappear, on=r3);
-    anim!(@slide, r3 => Target(0., 0.), on=r3);
+        ])
+        .z(z!(3))
+        .handle();
+    slide.main().animate(r0, Effect::Appear);
+    slide.on(r0).animate(r1, Effect::Appear);
+    slide.on(r0).animate(r0, Effect::Disappear);
+    slide
+        .on(r2)
+        .animate(r2, MacroEffect::Target(1., 1.))
+        .animate(r2, MacroEffect::Target(1., 5.))
+        .animate(r2, MacroEffect::Place);
+    slide.on(r1).animate(r3, Effect::Appear);
+    slide
+        .on(r3)
+        .animate(r3, MacroEffect::Target(1., 1.))
+        .animate(r3, Effect::Disappear)
+        .animate(r3, MacroEffect::Target(0., 0.));
 
     slide
 }
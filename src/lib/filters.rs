@@ -1,74 +1,188 @@
+use std::{
+    fmt,
+    hash::Hash,
+    ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Shl},
+};
+
 pub type Cell = usize;
-pub const CELL_SIZE: usize = std::mem::size_of::<Cell>() * 8;
-pub const CELL_SHIFT: usize = CELL_SIZE.trailing_zeros() as usize;
-pub const CELL_MASK: usize = CELL_SIZE - 1;
+pub const CELL_SIZE: usize = Cell::SIZE;
+pub const CELL_SHIFT: usize = Cell::SHIFT;
+pub const CELL_MASK: usize = Cell::MASK;
 
-#[derive(Clone, Debug)]
-pub struct Filter {
-    cells: Vec<Cell>,
+mod sealed {
+    pub trait Sealed {}
 }
-impl Filter {
+
+// Unsigned integer the filters pack their bits in, wider words mean fewer
+// cells to scan and to hash but coarser cell operations. Sealed: the
+// vectorised operations rely on blocks of words filling 256 bits exactly
+pub trait Word:
+    sealed::Sealed
+    + Copy
+    + Default
+    + Eq
+    + Hash
+    + fmt::Debug
+    + fmt::Binary
+    + fmt::LowerHex
+    + Send
+    + Sync
+    + 'static
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + BitXor<Output = Self>
+    + Not<Output = Self>
+    + BitAndAssign
+    + BitOrAssign
+    + BitXorAssign
+    + Shl<usize, Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const MAX: Self;
+    const SIZE: usize;
+    const SHIFT: usize = Self::SIZE.trailing_zeros() as usize;
+    const MASK: usize = Self::SIZE - 1;
+
+    fn leading_zeros(self) -> u32;
+    // 32 bits chunk of the word, from the lowest one, words of any size hash
+    // the same through them
+    fn chunk(self, index: usize) -> u32;
+
+    #[inline(always)]
+    fn bit(n: usize) -> Self {
+        Self::ONE << (n & Self::MASK)
+    }
+}
+
+macro_rules! word {
+    ($($word:ty),*) => {$(
+        impl sealed::Sealed for $word {}
+        impl Word for $word {
+            const ZERO: Self = 0;
+            const ONE: Self = 1;
+            const MAX: Self = <$word>::MAX;
+            const SIZE: usize = <$word>::BITS as usize;
+
+            #[inline(always)]
+            fn leading_zeros(self) -> u32 {
+                <$word>::leading_zeros(self)
+            }
+            #[inline(always)]
+            fn chunk(self, index: usize) -> u32 {
+                (self >> (index * 32)) as u32
+            }
+        }
+    )*};
+}
+word!(u32, u64, u128, usize);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Filter<W: Word = Cell> {
+    pub cells: Vec<W>,
+}
+impl<W: Word> Filter<W> {
     pub fn new(n: usize) -> Self {
-        let capacity = (n + CELL_SIZE - 1) / CELL_SIZE;
+        let capacity = n.div_ceil(W::SIZE);
         Self {
-            cells: vec![0; capacity],
+            cells: vec![W::ZERO; capacity],
         }
     }
 
     #[inline(always)]
     pub fn get(&self, n: usize) -> bool {
-        let index = n >> CELL_SHIFT;
-        self.cells[index] & (1 << (n & CELL_MASK)) != 0
+        let index = n >> W::SHIFT;
+        self.cells[index] & W::bit(n) != W::ZERO
     }
     #[inline(always)]
     pub fn set(&mut self, n: usize) {
-        let index = n >> CELL_SHIFT;
-        self.cells[index] |= 1 << (n & CELL_MASK);
+        let index = n >> W::SHIFT;
+        self.cells[index] |= W::bit(n);
     }
     #[inline(always)]
     pub fn unset(&mut self, n: usize) {
-        let index = n >> CELL_SHIFT;
-        self.cells[index] &= !(1 << (n & CELL_MASK));
+        let index = n >> W::SHIFT;
+        self.cells[index] &= !W::bit(n);
     }
     #[inline(always)]
     pub fn toggle(&mut self, n: usize) {
-        let index = n >> CELL_SHIFT;
-        self.cells[index] ^= 1 << (n & CELL_MASK);
+        let index = n >> W::SHIFT;
+        self.cells[index] ^= W::bit(n);
+    }
+
+    pub fn cells(&self) -> usize {
+        self.cells.len()
     }
 
     pub fn last(&self) -> Option<usize> {
         for (index, val) in self.cells.iter().enumerate().rev() {
-            if *val != 0 {
-                return Some((index << CELL_SHIFT) + CELL_SIZE - 1 - val.leading_zeros() as usize);
+            if *val != W::ZERO {
+                return Some(last_bit(index, *val));
             }
         }
         None
     }
+    // Last index set in both filters
+    pub fn last_and(&self, other: &Self) -> Option<usize> {
+        simd::last_and(&self.cells, &other.cells)
+    }
+    // Sets then unsets the bits of the masks, cell by cell
+    pub fn apply(&mut self, set: &Self, unset: &Self) {
+        simd::apply(&mut self.cells, &set.cells, &unset.cells)
+    }
+    // Indices set, from the last one
+    pub fn rev_iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.cells
+            .iter()
+            .enumerate()
+            .rev()
+            .flat_map(|(index, val)| rev_bits(index, *val))
+    }
+    // Indices set in both filters, from the last one
+    pub fn rev_iter_and<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = usize> + 'a {
+        self.cells
+            .iter()
+            .zip(&other.cells)
+            .enumerate()
+            .rev()
+            .flat_map(|(index, (a, b))| rev_bits(index, *a & *b))
+    }
+}
+
+fn rev_bits<W: Word>(index: usize, mut val: W) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if val == W::ZERO {
+            return None;
+        }
+        let bit = W::SIZE - 1 - val.leading_zeros() as usize;
+        val &= !W::bit(bit);
+        Some((index << W::SHIFT) + bit)
+    })
 }
 
 #[derive(Clone, Debug)]
-pub struct DoubleFilter {
-    pub cells: Vec<(Cell, Cell)>,
+pub struct DoubleFilter<W: Word = Cell> {
+    pub cells: Vec<(W, W)>,
 }
-impl DoubleFilter {
+impl<W: Word> DoubleFilter<W> {
     pub fn new(n: usize) -> Self {
-        let capacity = (n + CELL_SIZE - 1) / CELL_SIZE;
+        let capacity = n.div_ceil(W::SIZE);
         Self {
-            cells: vec![(0, 0); capacity],
+            cells: vec![(W::ZERO, W::ZERO); capacity],
         }
     }
 
     #[inline(always)]
     pub fn get(&self, n: usize) -> (bool, bool) {
-        let index = n >> CELL_SHIFT;
-        let sub_index = 1 << (n & CELL_MASK);
+        let index = n >> W::SHIFT;
+        let sub_index = W::bit(n);
         let cell = &self.cells[index];
-        (cell.0 & sub_index != 0, cell.1 & sub_index != 0)
+        (cell.0 & sub_index != W::ZERO, cell.1 & sub_index != W::ZERO)
     }
     #[inline(always)]
     pub fn set(&mut self, n: usize, first: bool, second: bool) {
-        let index = n >> CELL_SHIFT;
-        let set_mask = 1 << (n & CELL_MASK);
+        let index = n >> W::SHIFT;
+        let set_mask = W::bit(n);
         let unset_mask = !set_mask;
         let cell = &mut self.cells[index];
         if first {
@@ -84,14 +198,235 @@ impl DoubleFilter {
     }
 
     pub fn last(&self) -> Option<usize> {
-        for (index, val) in self.cells.iter().enumerate().rev() {
+        simd::last_pair(&self.cells)
+    }
+}
+
+fn last_bit<W: Word>(index: usize, val: W) -> usize {
+    (index << W::SHIFT) + W::SIZE - 1 - val.leading_zeros() as usize
+}
+
+// Reference versions of the vectorised operations, used where the target has
+// no vector instructions for them
+pub mod scalar {
+    use super::{last_bit, Word};
+
+    pub fn last_and<W: Word>(a: &[W], b: &[W]) -> Option<usize> {
+        for (index, (a, b)) in a.iter().zip(b).enumerate().rev() {
+            let combined = *a & *b;
+            if combined != W::ZERO {
+                return Some(last_bit(index, combined));
+            }
+        }
+        None
+    }
+
+    pub fn last_pair<W: Word>(cells: &[(W, W)]) -> Option<usize> {
+        for (index, val) in cells.iter().enumerate().rev() {
             let combined = val.0 & val.1;
-            if combined != 0 {
-                return Some(
-                    (index << CELL_SHIFT) + CELL_SIZE - 1 - combined.leading_zeros() as usize,
-                );
+            if combined != W::ZERO {
+                return Some(last_bit(index, combined));
             }
         }
         None
     }
+
+    pub fn apply<W: Word>(cells: &mut [W], set: &[W], unset: &[W]) {
+        for (cell, (set, unset)) in cells.iter_mut().zip(set.iter().zip(unset)) {
+            *cell = (*cell | *set) & !*unset;
+        }
+    }
+}
+
+// AVX2 versions handling 256 bits at a time, picked at runtime
+#[cfg(target_arch = "x86_64")]
+mod simd {
+    use std::{arch::x86_64::*, mem::size_of};
+
+    use super::{scalar, Word};
+
+    const BYTES: usize = 32;
+
+    pub fn last_and<W: Word>(a: &[W], b: &[W]) -> Option<usize> {
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: avx2 is available
+            unsafe { last_and_avx2(a, b) }
+        } else {
+            scalar::last_and(a, b)
+        }
+    }
+
+    pub fn last_pair<W: Word>(cells: &[(W, W)]) -> Option<usize> {
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: avx2 is available
+            unsafe { last_pair_avx2(cells) }
+        } else {
+            scalar::last_pair(cells)
+        }
+    }
+
+    pub fn apply<W: Word>(cells: &mut [W], set: &[W], unset: &[W]) {
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: avx2 is available
+            unsafe { apply_avx2(cells, set, unset) }
+        } else {
+            scalar::apply(cells, set, unset)
+        }
+    }
+
+    // The words are u32 to u128 wide, a block of lanes cells or of pairs is
+    // 256 bits and starts on an index the block length divides
+
+    // Scans blocks of cells from the end, the block holding the last index is
+    // searched again cell by cell
+    #[target_feature(enable = "avx2")]
+    unsafe fn last_and_avx2<W: Word>(a: &[W], b: &[W]) -> Option<usize> {
+        let lanes = BYTES / size_of::<W>();
+        let len = a.len().min(b.len());
+        let mut end = len;
+        while end >= lanes {
+            let start = end - lanes;
+            // SAFETY: start..end is in bounds of both slices
+            let (va, vb) = unsafe {
+                (
+                    _mm256_loadu_si256(a.as_ptr().add(start) as *const __m256i),
+                    _mm256_loadu_si256(b.as_ptr().add(start) as *const __m256i),
+                )
+            };
+            let combined = _mm256_and_si256(va, vb);
+            if _mm256_testz_si256(combined, combined) == 0 {
+                let last = scalar::last_and(&a[start..end], &b[start..end]);
+                return last.map(|index| index + (start << W::SHIFT));
+            }
+            end = start;
+        }
+        scalar::last_and(&a[..end], &b[..end])
+    }
+
+    // Each word of the block ANDed with the other one of its pair
+    #[target_feature(enable = "avx2")]
+    unsafe fn last_pair_avx2<W: Word>(cells: &[(W, W)]) -> Option<usize> {
+        let pairs = BYTES / size_of::<(W, W)>();
+        let mut end = cells.len();
+        while end >= pairs {
+            let start = end - pairs;
+            // SAFETY: the pairs start..end are in bounds and fill the block
+            let block = unsafe { _mm256_loadu_si256(cells.as_ptr().add(start) as *const __m256i) };
+            let swapped = match size_of::<W>() {
+                4 => _mm256_shuffle_epi32::<0b10_11_00_01>(block),
+                8 => _mm256_permute4x64_epi64::<0b10_11_00_01>(block),
+                _ => _mm256_permute4x64_epi64::<0b01_00_11_10>(block),
+            };
+            let combined = _mm256_and_si256(block, swapped);
+            if _mm256_testz_si256(combined, combined) == 0 {
+                let last = scalar::last_pair(&cells[start..end]);
+                return last.map(|index| index + (start << W::SHIFT));
+            }
+            end = start;
+        }
+        scalar::last_pair(&cells[..end])
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn apply_avx2<W: Word>(cells: &mut [W], set: &[W], unset: &[W]) {
+        let lanes = BYTES / size_of::<W>();
+        let len = cells.len().min(set.len()).min(unset.len());
+        let blocks = len / lanes * lanes;
+        for start in (0..blocks).step_by(lanes) {
+            // SAFETY: start..start + lanes is in bounds of the three slices
+            unsafe {
+                let cell = cells.as_mut_ptr().add(start) as *mut __m256i;
+                let vset = _mm256_loadu_si256(set.as_ptr().add(start) as *const __m256i);
+                let vunset = _mm256_loadu_si256(unset.as_ptr().add(start) as *const __m256i);
+                let value = _mm256_or_si256(_mm256_loadu_si256(cell), vset);
+                _mm256_storeu_si256(cell, _mm256_andnot_si256(vunset, value));
+            }
+        }
+        scalar::apply(&mut cells[blocks..len], &set[blocks..], &unset[blocks..]);
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+use scalar as simd;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        experiments::{self, RULE110_CLOCK},
+        runners::{minimal, Config, Runner},
+    };
+
+    // The vectorised filter operations agree with the scalar ones all along the
+    // rule110 show
+    #[test]
+    fn rule110() {
+        let config = Config {
+            target: RULE110_CLOCK,
+            ..Config::default()
+        };
+        let mut minimal = <minimal::Presentation>::build(experiments::rule110(), config).unwrap();
+        for _ in 0..2000 {
+            let state = &minimal.state;
+            for plane in &state.planes {
+                assert_eq!(
+                    state.visible.last_and(plane),
+                    scalar::last_and(&state.visible.cells, &plane.cells)
+                );
+                let mut applied = state.visible.clone();
+                applied.apply(plane, &state.planes[0]);
+                let mut cells = state.visible.cells.clone();
+                scalar::apply(&mut cells, &plane.cells, &state.planes[0].cells);
+                assert_eq!(applied.cells, cells);
+            }
+            minimal.click(RULE110_CLOCK.0, RULE110_CLOCK.1).unwrap();
+        }
+    }
+
+    // Filters of a few blocks, from dense to sparse, the last bits in common
+    // landing in the first, the middle or the trailing cells of the
+    // vectorised loops
+    fn widths<W: Word>() {
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut random = |below: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % below
+        };
+        for cells in 0..20 {
+            for _ in 0..20 {
+                let n = cells * W::SIZE;
+                let density = 1 + random(2 * W::SIZE as u64);
+                let mut filters = [(); 4].map(|_| Filter::<W>::new(n));
+                for filter in &mut filters {
+                    for bit in 0..n {
+                        if random(density) == 0 {
+                            filter.set(bit);
+                        }
+                    }
+                }
+                let [a, b, set, unset] = filters;
+                assert_eq!(a.last_and(&b), scalar::last_and(&a.cells, &b.cells));
+                let pairs = a.cells.iter().copied().zip(b.cells.iter().copied());
+                let pairs = pairs.collect::<Vec<_>>();
+                assert_eq!(simd::last_pair(&pairs), scalar::last_pair(&pairs));
+                let mut applied = a.clone();
+                applied.apply(&set, &unset);
+                let mut cells = a.cells.clone();
+                scalar::apply(&mut cells, &set.cells, &unset.cells);
+                assert_eq!(applied.cells, cells);
+            }
+        }
+    }
+
+    // The vectorised filter operations agree with the scalar ones on every
+    // word width
+    #[test]
+    fn words() {
+        widths::<u32>();
+        widths::<u64>();
+        widths::<u128>();
+        widths::<usize>();
+    }
 }
@@ -0,0 +1,74 @@
+// Uniform grid over the slide, each cell lists the shapes whose rectangle
+// overlaps it in increasing index order, so the topmost shape comes last
+#[derive(Clone, Debug)]
+pub struct Grid {
+    size: f32,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Vec<usize>>,
+}
+
+impl Grid {
+    pub fn new(width: f32, height: f32, size: f32) -> Self {
+        let columns = ((width / size).ceil() as usize).max(1);
+        let rows = ((height / size).ceil() as usize).max(1);
+        Self {
+            size,
+            columns,
+            rows,
+            cells: vec![Vec::new(); columns * rows],
+        }
+    }
+
+    // Range of cells covered by the rectangle, edges included, clipped to the grid
+    fn span(&self, (x, y, w, h): (f32, f32, f32, f32)) -> Option<(usize, usize, usize, usize)> {
+        let (x0, y0) = ((x / self.size).floor(), (y / self.size).floor());
+        let (x1, y1) = (((x + w) / self.size).floor(), ((y + h) / self.size).floor());
+        let (columns, rows) = (self.columns as f32, self.rows as f32);
+        if !(x1 >= 0. && y1 >= 0. && x0 < columns && y0 < rows) {
+            return None;
+        }
+        Some((
+            x0.max(0.) as usize,
+            y0.max(0.) as usize,
+            x1.min(columns - 1.) as usize,
+            y1.min(rows - 1.) as usize,
+        ))
+    }
+
+    pub fn insert(&mut self, index: usize, rect: (f32, f32, f32, f32)) {
+        let Some((x0, y0, x1, y1)) = self.span(rect) else {
+            return;
+        };
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let cell = &mut self.cells[y * self.columns + x];
+                if let Err(position) = cell.binary_search(&index) {
+                    cell.insert(position, index);
+                }
+            }
+        }
+    }
+
+    pub fn remove(&mut self, index: usize, rect: (f32, f32, f32, f32)) {
+        let Some((x0, y0, x1, y1)) = self.span(rect) else {
+            return;
+        };
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let cell = &mut self.cells[y * self.columns + x];
+                if let Ok(position) = cell.binary_search(&index) {
+                    cell.remove(position);
+                }
+            }
+        }
+    }
+
+    // Shapes that may contain the point, topmost last
+    pub fn candidates(&self, x: f32, y: f32) -> &[usize] {
+        match self.span((x, y, 0., 0.)) {
+            Some((x, y, _, _)) => &self.cells[y * self.columns + x],
+            None => &[],
+        }
+    }
+}
@@ -0,0 +1,40 @@
+// Zobrist-style state hashing: the hash of a state is the XOR of one key per
+// component value, so a runner updates it with two keys when a component
+// changes instead of rehashing everything
+
+use crate::filters::Word;
+
+pub const SHAPE: u64 = 0;
+pub const HEAD: u64 = 1;
+pub const VISIBLE: u64 = 2;
+pub const PLACE: u64 = 3;
+
+// splitmix64 finaliser
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+// Key of the component of the given kind and index holding the value,
+// components left at zero add nothing so untouched parts need no hashing
+pub fn key(kind: u64, index: usize, value: u64) -> u64 {
+    if value == 0 {
+        return 0;
+    }
+    mix(((index as u64) << 2 | kind).wrapping_mul(0xD1B54A32D192ED03) ^ value)
+}
+
+pub fn point(x: f32, y: f32) -> u64 {
+    (x.to_bits() as u64) << 32 | y.to_bits() as u64
+}
+
+// Keys of the 32 bits chunks of a filter cell, so that filters hash the same
+// whatever word they are packed in
+pub fn cell<W: Word>(kind: u64, index: usize, value: W) -> u64 {
+    let chunks = W::SIZE / 32;
+    (0..chunks).fold(0, |hash, chunk| {
+        hash ^ key(kind, index * chunks + chunk, value.chunk(chunk) as u64)
+    })
+}
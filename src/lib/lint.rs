@@ -0,0 +1,608 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{runners::RunnerKind, Effect, Preset, Shape, Slide, Timeline, Z};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Named {
+    pub id: usize,
+    pub name: &'static str,
+}
+
+#[derive(Clone, Debug)]
+pub enum Lint {
+    ClickOffSlide {
+        x: f32,
+        y: f32,
+    },
+    TriggerOffSlide {
+        shape: Named,
+        x: f32,
+        y: f32,
+    },
+    DanglingTarget {
+        trigger: Option<Named>,
+        referer: usize,
+    },
+    DanglingTrigger {
+        referer: usize,
+    },
+    ForeignReferer {
+        referer: usize,
+    },
+    CoveredTrigger {
+        shape: Named,
+        cover: Named,
+    },
+    HiddenTrigger {
+        shape: Named,
+    },
+    AmbiguousZ {
+        z: Z,
+        shapes: Vec<Named>,
+    },
+    UnsupportedEffect {
+        shape: Named,
+        effect: &'static str,
+        runner: RunnerKind,
+    },
+    UnsupportedShape {
+        shape: Named,
+        runner: RunnerKind,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub lint: Lint,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+impl fmt::Display for Named {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.name.is_empty() {
+            f.write_fmt(format_args!("shape {}", self.id))
+        } else {
+            f.write_fmt(format_args!("shape {} {:?}", self.id, self.name))
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!("{}: ", self.severity))?;
+        match &self.lint {
+            Lint::ClickOffSlide { x, y } => f.write_fmt(format_args!(
+                "click at ({x}, {y}) is outside the slide and will be ignored"
+            )),
+            Lint::TriggerOffSlide { shape, x, y } => f.write_fmt(format_args!(
+                "trigger {shape} placed at ({x}, {y}) is partly outside the slide, \
+                 clicks there will not reach it"
+            )),
+            Lint::DanglingTarget {
+                trigger: Some(trigger),
+                referer,
+            } => f.write_fmt(format_args!(
+                "animation triggered by {trigger} targets non-existent shape {referer}"
+            )),
+            Lint::DanglingTarget {
+                trigger: None,
+                referer,
+            } => f.write_fmt(format_args!(
+                "animation of the main sequence targets non-existent shape {referer}"
+            )),
+            Lint::DanglingTrigger { referer } => f.write_fmt(format_args!(
+                "animations are triggered by non-existent shape {referer}"
+            )),
+            Lint::ForeignReferer { referer } => f.write_fmt(format_args!(
+                "the timeline refers to shape {referer} of another slide"
+            )),
+            Lint::CoveredTrigger { shape, cover } => f.write_fmt(format_args!(
+                "trigger {shape} is always covered by {cover} and can never be clicked"
+            )),
+            Lint::HiddenTrigger { shape } => f.write_fmt(format_args!(
+                "trigger {shape} starts hidden and nothing shows it, it can never be clicked"
+            )),
+            Lint::AmbiguousZ { z, shapes } => {
+                f.write_fmt(format_args!("overlapping shapes share {z:?}:"))?;
+                for shape in shapes {
+                    f.write_fmt(format_args!(" {shape}"))?;
+                }
+                Ok(())
+            }
+            Lint::UnsupportedEffect {
+                shape,
+                effect,
+                runner,
+            } => f.write_fmt(format_args!(
+                "{effect} on {shape} is not supported by the {runner:?} runner"
+            )),
+            Lint::UnsupportedShape { shape, runner } => f.write_fmt(format_args!(
+                "{shape} is not supported by the {runner:?} runner"
+            )),
+        }
+    }
+}
+
+fn named(slide: &Slide, id: usize) -> Named {
+    Named {
+        id,
+        name: slide.shapes[id].1.name(),
+    }
+}
+
+fn overlap(a: (f32, f32, f32, f32), b: (f32, f32, f32, f32)) -> bool {
+    a.0 < b.0 + b.2 && b.0 < a.0 + a.2 && a.1 < b.1 + b.3 && b.1 < a.1 + a.3
+}
+
+fn inside(a: (f32, f32, f32, f32), b: (f32, f32, f32, f32)) -> bool {
+    a.0 >= b.0 && a.1 >= b.1 && a.0 + a.2 <= b.0 + b.2 && a.1 + a.3 <= b.1 + b.3
+}
+
+// Top-left corners each shape can be moved to on the slide, starting with
+// its home position
+pub fn positions(shapes: &[(usize, Shape)], timeline: &Timeline) -> Vec<Vec<(f32, f32)>> {
+    let mut positions = shapes
+        .iter()
+        .map(|(_, shape)| {
+            let (x, y, _, _) = shape.bounds();
+            vec![(x, y)]
+        })
+        .collect::<Vec<_>>();
+    for animation in timeline.animations() {
+        let Some(shape_positions) = positions.get_mut(animation.target.index()) else {
+            continue;
+        };
+        let (hx, hy) = shape_positions[0];
+        let position = match animation.effect {
+            Effect::Path {
+                x,
+                y,
+                relative: true,
+                ..
+            } => (hx + x, hy + y),
+            Effect::Path {
+                x,
+                y,
+                relative: false,
+                ..
+            } => (x, y),
+            _ => continue,
+        };
+        if !shape_positions.contains(&position) {
+            shape_positions.push(position);
+        }
+    }
+    positions
+}
+
+pub fn off_slide(slide: &Slide, clicks: &[(f32, f32)]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for &(x, y) in clicks {
+        if !slide.contains(x, y) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                lint: Lint::ClickOffSlide { x, y },
+            });
+        }
+    }
+    let positions = positions(&slide.shapes, &slide.timeline);
+    for (id, context) in slide.timeline.contexts.iter().enumerate() {
+        if context.animations.is_empty() || id >= slide.shapes.len() {
+            continue;
+        }
+        let (_, _, w, h) = slide.shapes[id].1.bounds();
+        for &(x, y) in &positions[id] {
+            if x < 0. || y < 0. || x + w > slide.width || y + h > slide.height {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Warning,
+                    lint: Lint::TriggerOffSlide {
+                        shape: named(slide, id),
+                        x,
+                        y,
+                    },
+                });
+            }
+        }
+    }
+    diagnostics
+}
+
+fn dangling(slide: &Slide, diagnostics: &mut Vec<Diagnostic>) {
+    for referer in slide.foreign() {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            lint: Lint::ForeignReferer {
+                referer: referer.index(),
+            },
+        });
+    }
+    let shapes = slide.shapes.len();
+    let contexts = slide
+        .timeline
+        .contexts
+        .iter()
+        .enumerate()
+        .map(|(id, context)| (Some(id), context))
+        .chain([(None, &slide.timeline.main_context)]);
+    for (trigger, context) in contexts {
+        if context.animations.is_empty() {
+            continue;
+        }
+        if let Some(referer) = trigger.filter(|id| *id >= shapes) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                lint: Lint::DanglingTrigger { referer },
+            });
+            continue;
+        }
+        for animation in &context.animations {
+            let referer = animation.target.index();
+            if referer >= shapes {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    lint: Lint::DanglingTarget {
+                        trigger: trigger.map(|id| named(slide, id)),
+                        referer,
+                    },
+                });
+            }
+        }
+    }
+}
+
+// A trigger is dead if, wherever it goes, a shape above it that no animation
+// ever touches covers it entirely. Returns the dead triggers with their cover
+pub fn covered_triggers(
+    shapes: &[(usize, Shape)],
+    timeline: &Timeline,
+    positions: &[Vec<(f32, f32)>],
+) -> Vec<(usize, usize)> {
+    let mut animated = vec![false; shapes.len()];
+    for animation in timeline.animations() {
+        if let Some(animated) = animated.get_mut(animation.target.index()) {
+            *animated = true;
+        }
+    }
+    let covers = (0..shapes.len())
+        .filter(|id| !animated[*id])
+        .map(|id| (id, shapes[id].1.z(), shapes[id].1.bounds()))
+        .collect::<Vec<_>>();
+    let mut triggers = Vec::new();
+    for (id, context) in timeline.contexts.iter().enumerate() {
+        if context.animations.is_empty() || id >= shapes.len() {
+            continue;
+        }
+        let shape = &shapes[id].1;
+        let (_, _, w, h) = shape.bounds();
+        let cover = covers.iter().find(|(_, z, bounds)| {
+            *z < shape.z()
+                && positions[id]
+                    .iter()
+                    .all(|&(x, y)| inside((x, y, w, h), *bounds))
+        });
+        if let Some((cover, _, _)) = cover {
+            triggers.push((id, *cover));
+        }
+    }
+    triggers
+}
+
+fn covered(slide: &Slide, positions: &[Vec<(f32, f32)>], diagnostics: &mut Vec<Diagnostic>) {
+    for (id, cover) in covered_triggers(&slide.shapes, &slide.timeline, positions) {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            lint: Lint::CoveredTrigger {
+                shape: named(slide, id),
+                cover: named(slide, cover),
+            },
+        });
+    }
+}
+
+// A trigger starting hidden can only be clicked once an entrance effect of a
+// sequence that can itself be fired shows it. Those sequences are found from
+// the main one, contexts of covered triggers never firing. Returns the
+// triggers left hidden
+pub fn hidden_triggers(slide: &Slide, positions: &[Vec<(f32, f32)>]) -> Vec<usize> {
+    let shapes = slide.shapes.len();
+    // the first effect of a shape decides whether it starts hidden, the
+    // runners visiting the main sequence first
+    let mut hidden = vec![None; shapes];
+    let contexts = slide.timeline.contexts.iter();
+    for context in [&slide.timeline.main_context].into_iter().chain(contexts) {
+        for animation in &context.animations {
+            if let Some(hidden @ None) = hidden.get_mut(animation.target.index()) {
+                *hidden = Some(matches!(animation.effect.preset(), Preset::Entr(_, _)));
+            }
+        }
+    }
+    let mut visible = hidden
+        .iter()
+        .map(|hidden| *hidden != Some(true))
+        .collect::<Vec<_>>();
+    let covered = covered_triggers(&slide.shapes, &slide.timeline, positions);
+    let mut fired = vec![false; slide.timeline.contexts.len()];
+    let mut pending = vec![&slide.timeline.main_context];
+    while let Some(context) = pending.pop() {
+        for animation in &context.animations {
+            let id = animation.target.index();
+            if id >= shapes
+                || visible[id]
+                || !matches!(animation.effect.preset(), Preset::Entr(_, _))
+            {
+                continue;
+            }
+            visible[id] = true;
+        }
+        for (id, context) in slide.timeline.contexts.iter().enumerate().take(shapes) {
+            let covered = covered.iter().any(|(trigger, _)| *trigger == id);
+            if visible[id] && !fired[id] && !covered {
+                fired[id] = true;
+                pending.push(context);
+            }
+        }
+    }
+    slide
+        .timeline
+        .contexts
+        .iter()
+        .enumerate()
+        .take(shapes)
+        .filter(|(id, context)| !context.animations.is_empty() && !visible[*id])
+        .map(|(id, _)| id)
+        .collect()
+}
+
+fn hidden(slide: &Slide, positions: &[Vec<(f32, f32)>], diagnostics: &mut Vec<Diagnostic>) {
+    for id in hidden_triggers(slide, positions) {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            lint: Lint::HiddenTrigger {
+                shape: named(slide, id),
+            },
+        });
+    }
+}
+
+fn ambiguous(slide: &Slide, positions: &[Vec<(f32, f32)>], diagnostics: &mut Vec<Diagnostic>) {
+    let mut layers = BTreeMap::<Z, Vec<usize>>::new();
+    for (id, (_, shape)) in slide.shapes.iter().enumerate() {
+        layers.entry(shape.z()).or_default().push(id);
+    }
+    for (z, ids) in layers {
+        let rects = ids
+            .iter()
+            .map(|id| {
+                let (_, _, w, h) = slide.shapes[*id].1.bounds();
+                positions[*id]
+                    .iter()
+                    .map(|&(x, y)| (x, y, w, h))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let mut shapes = Vec::new();
+        for (i, id) in ids.iter().enumerate() {
+            let overlapping = (0..ids.len()).filter(|j| *j != i).any(|j| {
+                rects[i]
+                    .iter()
+                    .any(|a| rects[j].iter().any(|b| overlap(*a, *b)))
+            });
+            if overlapping {
+                shapes.push(named(slide, *id));
+            }
+        }
+        if !shapes.is_empty() {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                lint: Lint::AmbiguousZ { z, shapes },
+            });
+        }
+    }
+}
+
+fn unsupported(slide: &Slide, runner: RunnerKind, diagnostics: &mut Vec<Diagnostic>) {
+    for (id, (_, shape)) in slide.shapes.iter().enumerate() {
+        if !runner.supports_shape(shape) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                lint: Lint::UnsupportedShape {
+                    shape: named(slide, id),
+                    runner,
+                },
+            });
+        }
+    }
+    for animation in slide.timeline.animations() {
+        let id = animation.target.index();
+        if id < slide.shapes.len() && !runner.supports_effect(&animation.effect) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                lint: Lint::UnsupportedEffect {
+                    shape: named(slide, id),
+                    effect: animation.effect.name(),
+                    runner,
+                },
+            });
+        }
+    }
+}
+
+pub fn validate(slide: &Slide, runner: RunnerKind) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let positions = positions(&slide.shapes, &slide.timeline);
+    dangling(slide, &mut diagnostics);
+    covered(slide, &positions, &mut diagnostics);
+    hidden(slide, &positions, &mut diagnostics);
+    ambiguous(slide, &positions, &mut diagnostics);
+    unsupported(slide, runner, &mut diagnostics);
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Reverse;
+
+    use super::*;
+    use crate::{anim, shape, Referer, Shape};
+
+    fn lints(slide: &Slide, runner: RunnerKind) -> Vec<Lint> {
+        let mut diagnostics = validate(slide, runner);
+        diagnostics.sort_by_key(|diagnostic| Reverse(diagnostic.severity));
+        diagnostics.into_iter().map(|d| d.lint).collect()
+    }
+
+    #[test]
+    fn clean() {
+        let mut slide = Slide::new(40., 30.);
+        let trigger = shape!(@slide, 0, 0, 4, 4);
+        let target = shape!(@slide, 10, 0, 4, 4);
+        anim!(@slide, target => Appear, on=trigger);
+        assert!(lints(&slide, RunnerKind::Minimal).is_empty());
+    }
+
+    #[test]
+    fn dangling() {
+        let mut slide = Slide::new(40., 30.);
+        let trigger = shape!(@slide, 0, 0, 4, 4);
+        let effect = Effect::Disappear;
+        slide
+            .timeline
+            .add(Referer::Shape(5), effect.clone(), false, Some(trigger));
+        slide
+            .timeline
+            .add(trigger, effect, false, Some(Referer::Shape(7)));
+        let found = lints(&slide, RunnerKind::Basic);
+        assert!(matches!(
+            found[..],
+            [
+                Lint::DanglingTarget {
+                    trigger: Some(Named { id: 0, .. }),
+                    referer: 5
+                },
+                Lint::DanglingTrigger { referer: 7 },
+            ]
+        ));
+    }
+
+    #[test]
+    fn covered() {
+        let mut slide = Slide::new(40., 30.);
+        let trigger = shape!(@slide, 2, 2, 4, 4, Z=Z(1, 0, 0), n="button");
+        shape!(@slide, 0, 0, 10, 10, n="cover");
+        anim!(@slide, trigger => Path(1., 1.), on=trigger);
+        let found = lints(&slide, RunnerKind::Basic);
+        assert!(matches!(
+            found[..],
+            [Lint::CoveredTrigger {
+                shape: Named {
+                    id: 0,
+                    name: "button"
+                },
+                cover: Named {
+                    id: 1,
+                    name: "cover"
+                },
+            }]
+        ));
+        // moved out from under its cover, it is no longer dead
+        anim!(@slide, trigger => Path(20., 20.), c=true);
+        assert!(lints(&slide, RunnerKind::Basic).is_empty());
+    }
+
+    // Triggers shown by the main sequence, by a trigger shown by it, by
+    // themselves, and by a trigger never shown
+    #[test]
+    fn hidden() {
+        let mut slide = Slide::new(40., 30.);
+        let shown = shape!(@slide, 0, 0, 4, 4);
+        let chained = shape!(@slide, 10, 0, 4, 4);
+        let itself = shape!(@slide, 20, 0, 4, 4);
+        let orphan = shape!(@slide, 30, 0, 4, 4);
+        anim!(@slide, shown => Appear);
+        anim!(@slide, chained => Appear, on=shown);
+        anim!(@slide, itself => Appear, on=itself);
+        anim!(@slide, orphan => Appear, on=itself);
+        anim!(@slide, orphan => Disappear, on=orphan);
+        anim!(@slide, chained => Disappear, on=chained);
+        let found = lints(&slide, RunnerKind::Basic);
+        assert!(matches!(
+            found[..],
+            [
+                Lint::HiddenTrigger {
+                    shape: Named { id: 2, .. }
+                },
+                Lint::HiddenTrigger {
+                    shape: Named { id: 3, .. }
+                },
+            ]
+        ));
+    }
+
+    #[test]
+    fn ambiguous() {
+        let mut slide = Slide::new(40., 30.);
+        shape!(@slide, 0, 0, 4, 4);
+        let moving = shape!(@slide, 10, 0, 4, 4);
+        shape!(@slide, 20, 20, 4, 4, Z=Z(1, 0, 0));
+        assert!(lints(&slide, RunnerKind::Basic).is_empty());
+        // a path onto the first shape makes their order matter
+        anim!(@slide, moving => Target(2., 2.));
+        let found = lints(&slide, RunnerKind::Basic);
+        assert!(matches!(&found[..], [Lint::AmbiguousZ { shapes, .. }] if shapes.len() == 2));
+    }
+
+    #[test]
+    fn unsupported() {
+        let mut slide = Slide::new(40., 30.);
+        let group = slide.add(Shape::Group {
+            z: Z(0, 0, 0),
+            shapes: vec![shape!(0, 0, 4, 4)],
+        });
+        anim!(@slide, group => Mark);
+        assert!(matches!(
+            lints(&slide, RunnerKind::Basic)[..],
+            [Lint::UnsupportedEffect {
+                effect: "incomplete SlideOut",
+                ..
+            }]
+        ));
+        assert!(matches!(
+            lints(&slide, RunnerKind::Minimal)[..],
+            [
+                Lint::UnsupportedShape { .. },
+                Lint::UnsupportedEffect { .. },
+            ]
+        ));
+    }
+
+    #[test]
+    fn off_slide_clicks() {
+        let mut slide = Slide::new(40., 30.);
+        let trigger = shape!(@slide, 38, 0, 4, 4);
+        anim!(@slide, trigger => Disappear, on=trigger);
+        let diagnostics = off_slide(&slide, &[(40., 30.), (41., 0.)]);
+        let found = diagnostics.into_iter().map(|d| d.lint).collect::<Vec<_>>();
+        assert!(matches!(
+            found[..],
+            [
+                Lint::ClickOffSlide { x: 41., y: 0. },
+                Lint::TriggerOffSlide { x: 38., y: 0., .. },
+            ]
+        ));
+    }
+}
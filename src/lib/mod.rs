@@ -1,10 +1,25 @@
 use std::{fmt, io::BufRead};
 
+pub mod builder;
+pub mod codegen;
+pub mod diff;
+pub mod error;
 pub mod experiments;
 pub mod filters;
+pub mod grid;
+pub mod hash;
+pub mod lint;
+pub mod optimise;
+pub mod partition;
 pub mod render;
 pub mod runners;
 
+pub use error::{Error, Result};
+
+use lint::Diagnostic;
+use optimise::Pass;
+use runners::RunnerKind;
+
 //=========================================================
 // Debug
 
@@ -15,7 +30,7 @@ pub fn pause() {
 //=========================================================
 // Shape
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(C)]
 pub struct Color {
     pub r: u8,
@@ -36,7 +51,7 @@ impl Color {
         Self {
             r: ((c & 0xFF0000) >> 16) as u8,
             g: ((c & 0x00FF00) >> 8) as u8,
-            b: ((c & 0x0000FF) >> 0) as u8,
+            b: (c & 0x0000FF) as u8,
         }
     }
     pub const fn grey(c: u8) -> Self {
@@ -103,18 +118,40 @@ impl Shape {
             Shape::Group { shapes, .. } => shapes.iter().map(Shape::size).sum(),
         }
     }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shape::Shape { name, .. } => name,
+            Shape::Group { shapes, .. } => shapes.first().map_or("", Shape::name),
+        }
+    }
+    pub fn bounds(&self) -> (f32, f32, f32, f32) {
+        match self {
+            Shape::Shape { state, .. } => (state.x, state.y, state.w, state.h),
+            Shape::Group { shapes, .. } => {
+                let (mut x1, mut y1) = (f32::MAX, f32::MAX);
+                let (mut x2, mut y2) = (f32::MIN, f32::MIN);
+                for (x, y, w, h) in shapes.iter().map(Shape::bounds) {
+                    x1 = x1.min(x);
+                    y1 = y1.min(y);
+                    x2 = x2.max(x + w);
+                    y2 = y2.max(y + h);
+                }
+                (x1, y1, x2 - x1, y2 - y1)
+            }
+        }
+    }
 }
 
 #[macro_export]
 macro_rules! z {
     ($a:expr) => {
-        Z($a as isize, 0, 0)
+        $crate::Z($a as isize, 0, 0)
     };
     ($a:expr, $b:expr) => {
-        Z($a as isize, $b as isize, 0)
+        $crate::Z($a as isize, $b as isize, 0)
     };
     ($a:expr, $b:expr, $c:expr) => {
-        Z($a as isize, $b as isize, $c as isize)
+        $crate::Z($a as isize, $b as isize, $c as isize)
     };
 }
 
@@ -124,7 +161,7 @@ macro_rules! shape {
         $s.add(shape!{ $($t)* })
     };
     ($x:expr, $y:expr, $w:expr, $h:expr $(,Z=$Z:expr)? $(,z=($($z:expr),*))? $(,c=$c:expr)? $(,n=$n:expr)? $(,)?) => {{
-           let _z = Z(0,0,0);
+           let _z = $crate::Z(0,0,0);
         $( let _z = $Z; )?
         $( let _z = $crate::z!($($z),*); )?
            let _c = $crate::Color::BLACK;
@@ -179,10 +216,13 @@ macro_rules! shape {
 //=========================================================
 // Animation
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Referer {
     Shape(usize),
     Group(usize, usize),
+    // shape id handed out by the slide of the first id, which can then tell
+    // its own referers apart
+    Slide(usize, usize),
 }
 
 impl std::default::Default for Referer {
@@ -194,26 +234,26 @@ impl std::default::Default for Referer {
 impl Referer {
     pub fn index(&self) -> usize {
         match self {
-            Referer::Shape(index) => *index,
+            Referer::Shape(index) | Referer::Slide(_, index) => *index,
             Referer::Group(index, _) => *index,
         }
     }
     pub fn size(&self) -> usize {
         match self {
-            Referer::Shape(_) => 1,
+            Referer::Shape(_) | Referer::Slide(_, _) => 1,
             Referer::Group(_, size) => *size,
         }
     }
 
     pub fn span(&self) -> (usize, usize) {
         match self {
-            Referer::Shape(index) => (*index, 1),
+            Referer::Shape(index) | Referer::Slide(_, index) => (*index, 1),
             Referer::Group(index, size) => (*index, *size),
         }
     }
     pub fn bounds(&self) -> (usize, usize) {
         match self {
-            Referer::Shape(index) => (*index, *index + 1),
+            Referer::Shape(index) | Referer::Slide(_, index) => (*index, *index + 1),
             Referer::Group(index, size) => (*index, *index + *size),
         }
     }
@@ -256,6 +296,18 @@ pub enum Effect {
 }
 
 impl Effect {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Effect::Appear => "Appear",
+            Effect::Disappear => "Disappear",
+            Effect::SlideIn { .. } => "SlideIn",
+            Effect::SlideOut {
+                complete: false, ..
+            } => "incomplete SlideOut",
+            Effect::SlideOut { .. } => "SlideOut",
+            Effect::Path { .. } => "Path",
+        }
+    }
     pub fn preset(&self) -> Preset {
         match self {
             Effect::Appear => Preset::Entr(1, 0),
@@ -327,6 +379,14 @@ pub struct Animation {
 
 #[macro_export]
 macro_rules! anim {
+    (@$s:expr, $t:expr => Hot($k:expr) $(, c=$c:expr)? $(, on=$on:expr)?) => {{
+           let _c = false;
+        $( let _c = $c; )?
+           let _on = Option::<$crate::Referer>::None;
+        $( let _on = Some($on); )?
+        $s.tl_add_hot($t, $k, _c, _on)
+    }};
+
     (@$s:expr, $t:expr => $e:tt$(($($args:tt)+))? $(, c=$c:expr)? $(, on=$on:expr)?) => {
         anim!(@$s, $t => $crate::MacroEffect::$e$(($($args)+))? $(, c=$c)? $(, on=$on)?)
     };
@@ -374,17 +434,30 @@ impl Timeline {
             None => self.main_context.animations.push(animation),
         }
     }
+    pub fn animations(&self) -> impl Iterator<Item = &Animation> {
+        self.contexts
+            .iter()
+            .chain([&self.main_context])
+            .flat_map(|context| context.animations.iter())
+    }
 }
 
 //=========================================================
 // Slide
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Slide {
     pub shapes: Vec<(usize, Shape)>,
     pub timeline: Timeline,
     pub width: f32,
     pub height: f32,
+    // points clicked over and over, runners may answer them without a scan
+    pub hot_points: Vec<(f32, f32)>,
+    id: usize,
+    // referers of other slides the timeline was given, building fails on them
+    foreign: Vec<Referer>,
+    // hot point indices given to tl_add_hot that the slide did not hand out
+    unknown_hot: Vec<usize>,
 }
 
 impl Slide {
@@ -394,14 +467,197 @@ impl Slide {
             timeline: Timeline::default(),
             width,
             height,
+            hot_points: Vec::new(),
+            id: builder::next_slide_id(),
+            foreign: Vec::new(),
+            unknown_hot: Vec::new(),
         }
     }
+    // The referer is tagged with the slide, Referer::Slide rather than a bare
+    // Referer::Shape, so that the timeline can tell those of other slides
     pub fn add(&mut self, shape: Shape) -> Referer {
         let id = self.shapes.len();
         self.shapes.push((id, shape));
-        Referer::Shape(id)
+        Referer::Slide(self.id, id)
     }
     pub fn tl_add(&mut self, target: Referer, effect: Effect, click: bool, on: Option<Referer>) {
+        for referer in [Some(target), on].into_iter().flatten() {
+            if !self.owns(referer) {
+                self.foreign.push(referer);
+            }
+        }
         self.timeline.add(target, effect, click, on)
     }
+    // Adds a move of the target to the hot point of the given index. An index
+    // the slide did not hand out is recorded and building fails on it, the move
+    // added in its place is never played
+    pub fn tl_add_hot(&mut self, target: Referer, index: usize, click: bool, on: Option<Referer>) {
+        let effect = self.hot_target(index).unwrap_or_else(|_| {
+            self.unknown_hot.push(index);
+            MacroEffect::Target(0., 0.).into()
+        });
+        self.tl_add(target, effect, click, on)
+    }
+    // Whether the referer was handed out by this slide or is a bare index
+    pub fn owns(&self, referer: Referer) -> bool {
+        !matches!(referer, Referer::Slide(slide, _) if slide != self.id)
+    }
+    pub fn foreign(&self) -> &[Referer] {
+        &self.foreign
+    }
+    // Fails on the first referer of another slide the timeline was given, then
+    // on the first hot point index it did not hand out
+    pub fn check_referers(&self) -> Result<()> {
+        if let Some(referer) = self.foreign.first() {
+            return Err(Error::ForeignReferer {
+                referer: referer.index(),
+            });
+        }
+        match self.unknown_hot.first() {
+            Some(&index) => Err(Error::UnknownHotPoint {
+                index,
+                hot_points: self.hot_points.len(),
+            }),
+            None => Ok(()),
+        }
+    }
+    // Registers a hot point and returns its index
+    pub fn hot(&mut self, x: f32, y: f32) -> usize {
+        match self.hot_points.iter().position(|point| *point == (x, y)) {
+            Some(index) => index,
+            None => {
+                self.hot_points.push((x, y));
+                self.hot_points.len() - 1
+            }
+        }
+    }
+    // Moves a shape to the hot point of the given index
+    pub fn hot_target(&self, index: usize) -> Result<Effect> {
+        let &(x, y) = self.hot_points.get(index).ok_or(Error::UnknownHotPoint {
+            index,
+            hot_points: self.hot_points.len(),
+        })?;
+        Ok(MacroEffect::Target(x, y).into())
+    }
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        in_bounds(x, y, self.width, self.height)
+    }
+    pub fn validate(&self, runner: RunnerKind) -> Vec<Diagnostic> {
+        lint::validate(self, runner)
+    }
+    pub fn optimise(&mut self, passes: &[Pass]) -> Vec<optimise::Report> {
+        optimise::run(&mut self.timeline, &self.shapes, passes)
+    }
+}
+
+// PowerPoint only delivers clicks that land on the slide area, its edges
+// included like those of the shapes
+pub fn in_bounds(x: f32, y: f32, width: f32, height: f32) -> bool {
+    x >= 0. && y >= 0. && x <= width && y <= height
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runners::{basic, minimal, reference, Config, Runner};
+
+    // Two slides of the same size, their referers are in range of each other
+    fn slides() -> (Slide, Slide, Referer) {
+        let mut a = Slide::new(40., 30.);
+        let mut b = Slide::new(40., 30.);
+        shape!(@a, 0, 0, 4, 4);
+        let theirs = shape!(@a, 10, 0, 4, 4);
+        let ours = shape!(@b, 0, 0, 4, 4);
+        shape!(@b, 10, 0, 4, 4);
+        anim!(@b, ours => Appear, on=theirs);
+        (a, b, theirs)
+    }
+
+    #[test]
+    fn foreign_referer() {
+        let (a, b, theirs) = slides();
+        assert!(a.owns(theirs) && !b.owns(theirs));
+        assert_eq!(b.foreign(), [theirs]);
+        let error = Err(Error::ForeignReferer { referer: 1 });
+        let config = Config::default();
+        assert_eq!(
+            basic::Presentation::build(b.clone(), config).map(drop),
+            error
+        );
+        assert_eq!(
+            <minimal::Presentation>::build(b.clone(), config).map(drop),
+            error
+        );
+        assert_eq!(
+            reference::Presentation::build(b.clone(), config).map(drop),
+            error
+        );
+        let diagnostics = b.validate(RunnerKind::Basic);
+        assert!(diagnostics
+            .iter()
+            .any(|d| matches!(d.lint, lint::Lint::ForeignReferer { referer: 1 })));
+        assert!(a.check_referers().is_ok());
+    }
+
+    // The slide of a move to a hot point is evaluated once like in the other
+    // arms of anim!
+    #[test]
+    fn hot_slide_once() {
+        let mut slide = Slide::new(40., 30.);
+        let shape = shape!(@slide, 0, 0, 4, 4);
+        let hot = slide.hot(10., 20.);
+        let mut evaluated = 0;
+        anim!(@{ evaluated += 1; &mut slide }, shape => Hot(hot), c=true);
+        assert_eq!(evaluated, 1);
+        let animation = &slide.timeline.main_context.animations[0];
+        assert!(animation.click);
+        assert!(matches!(
+            animation.effect,
+            Effect::Path {
+                x: 10.,
+                y: 20.,
+                relative: false,
+                ..
+            }
+        ));
+    }
+
+    // A hot point index the slide did not hand out is an error, when asked for
+    // its move and when building a slide moving a shape there
+    #[test]
+    fn unknown_hot_point() {
+        let mut slide = Slide::new(40., 30.);
+        let shape = shape!(@slide, 0, 0, 4, 4);
+        let hot = slide.hot(10., 20.);
+        let error = Error::UnknownHotPoint {
+            index: hot + 1,
+            hot_points: 1,
+        };
+        assert_eq!(slide.hot_target(hot + 1).map(drop), Err(error.clone()));
+        anim!(@slide, shape => Hot(hot + 1), c=true);
+        assert_eq!(slide.check_referers(), Err(error.clone()));
+        assert_eq!(
+            <minimal::Presentation>::build(slide, Config::default()).map(drop),
+            Err(error)
+        );
+    }
+
+    // Handles of the builder carry their slide, using one on another slide
+    // fails to build instead of panicking
+    #[test]
+    fn foreign_handle() {
+        let mut a = Slide::new(40., 30.);
+        let mut b = Slide::new(40., 30.);
+        let theirs = a.rect(0., 0., 4., 4.).handle();
+        let ours = b.rect(0., 0., 4., 4.).name("ours").handle();
+        b.on(theirs).animate(ours, Effect::Disappear);
+        b.main().animate(ours, Effect::Appear);
+        assert_eq!(b.foreign(), [theirs.referer()]);
+        assert_eq!(
+            basic::Presentation::build(b, Config::default()).map(drop),
+            Err(Error::ForeignReferer { referer: 0 })
+        );
+        a.main().animate(theirs, Effect::Appear);
+        assert!(a.check_referers().is_ok());
+    }
 }
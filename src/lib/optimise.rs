@@ -0,0 +1,455 @@
+// Passes rewriting a timeline into a smaller one, which every runner and
+// exporter can then be given instead.
+//
+// Within a click group, effects only write the visibility and the position of
+// their shape and nothing reads them before the next click, so an effect
+// whose components are all overwritten later in its group is dead. A motion
+// path does not overwrite the position, the reference runner moving the shape
+// from wherever the earlier effects left it. The first effect of each shape
+// is always kept, it decides whether the shape starts hidden.
+
+use std::{collections::HashSet, fmt, ops::Range};
+
+use crate::{lint, Animation, Context, Effect, Shape, Timeline};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    // effects whose components are all written again later in their group
+    Overwritten,
+    // trigger sequences of shapes that can never be topmost, as the basic and
+    // minimal runners take paths from the home position, the reference runner
+    // accumulating relative paths can carry a trigger out from under its cover
+    DeadContexts,
+    // consecutive motion paths of a shape in a group, folded into the last
+    // one as the basic and minimal runners take paths from the home position,
+    // the reference runner accumulating them then plays the show differently
+    FoldPaths,
+    // click groups merged into the previous one when their effects commute,
+    // both then play on a single click so the show changes
+    MergeGroups,
+}
+
+impl Pass {
+    // passes leaving every click of the show as it was in every runner, in a
+    // working order
+    pub const SAFE: [Pass; 1] = [Pass::Overwritten];
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Removal {
+    // animation of the sequence of the trigger, the main one if None, by its
+    // index before the pass
+    Animation {
+        trigger: Option<usize>,
+        index: usize,
+    },
+    // animations of a dead trigger sequence, those deciding whether a shape
+    // starts hidden are kept
+    Context {
+        trigger: usize,
+        animations: usize,
+    },
+    // click group merged into the previous one, by its index before the pass
+    Group {
+        trigger: Option<usize>,
+        group: usize,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub pass: Pass,
+    pub removed: Vec<Removal>,
+}
+
+impl Report {
+    pub fn animations(&self) -> usize {
+        self.removed
+            .iter()
+            .map(|removal| match removal {
+                Removal::Animation { .. } => 1,
+                Removal::Context { animations, .. } => *animations,
+                Removal::Group { .. } => 0,
+            })
+            .sum()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let contexts = self.removed.iter();
+        let contexts = contexts.filter(|removal| matches!(removal, Removal::Context { .. }));
+        let groups = self.removed.iter();
+        let groups = groups.filter(|removal| matches!(removal, Removal::Group { .. }));
+        f.write_fmt(format_args!(
+            "{:?}: removed {} animations, {} contexts, {} click groups",
+            self.pass,
+            self.animations(),
+            contexts.count(),
+            groups.count()
+        ))
+    }
+}
+
+const VISIBILITY: u8 = 1;
+const POSITION: u8 = 2;
+
+// Components of its shape the effect writes
+fn writes(effect: &Effect) -> u8 {
+    match effect {
+        Effect::Appear | Effect::Disappear => VISIBILITY,
+        Effect::SlideIn { .. } | Effect::SlideOut { .. } => VISIBILITY | POSITION,
+        Effect::Path { .. } => POSITION,
+    }
+}
+
+// Components of its shape the effect writes whatever they were before
+fn overwrites(effect: &Effect) -> u8 {
+    match effect {
+        Effect::Path { .. } => 0,
+        effect => writes(effect),
+    }
+}
+
+// Click groups of a sequence, the click flag of its first animation is ignored
+fn groups(animations: &[Animation]) -> Vec<Range<usize>> {
+    let mut groups = Vec::new();
+    let mut start = 0;
+    for (index, animation) in animations.iter().enumerate().skip(1) {
+        if animation.click {
+            groups.push(start..index);
+            start = index;
+        }
+    }
+    if !animations.is_empty() {
+        groups.push(start..animations.len());
+    }
+    groups
+}
+
+fn sequences(timeline: &Timeline) -> impl Iterator<Item = (Option<usize>, &Context)> {
+    let contexts = timeline.contexts.iter().enumerate();
+    [(None, &timeline.main_context)]
+        .into_iter()
+        .chain(contexts.map(|(id, context)| (Some(id), context)))
+}
+
+fn sequences_mut(timeline: &mut Timeline) -> impl Iterator<Item = (Option<usize>, &mut Context)> {
+    let contexts = timeline.contexts.iter_mut().enumerate();
+    [(None, &mut timeline.main_context)]
+        .into_iter()
+        .chain(contexts.map(|(id, context)| (Some(id), context)))
+}
+
+// Location of the first effect of each shape, in the order the runners visit
+// them to decide which shapes start hidden
+fn firsts(timeline: &Timeline) -> HashSet<(Option<usize>, usize)> {
+    let mut seen = HashSet::new();
+    let mut firsts = HashSet::new();
+    for (trigger, context) in sequences(timeline) {
+        for (index, animation) in context.animations.iter().enumerate() {
+            if seen.insert(animation.target.index()) {
+                firsts.insert((trigger, index));
+            }
+        }
+    }
+    firsts
+}
+
+// Removes the marked animations, a removed one starting a click group hands
+// its flag to the next one
+fn remove(animations: &mut Vec<Animation>, dead: &[bool]) {
+    for index in 0..animations.len() {
+        if dead[index] && animations[index].click {
+            if let Some(next) = animations.get_mut(index + 1) {
+                next.click = true;
+            }
+        }
+    }
+    let mut dead = dead.iter();
+    animations.retain(|_| !dead.next().unwrap());
+}
+
+fn overwritten(timeline: &mut Timeline) -> Vec<Removal> {
+    let firsts = firsts(timeline);
+    let mut removed = Vec::new();
+    for (trigger, context) in sequences_mut(timeline) {
+        let animations = &mut context.animations;
+        let mut dead = vec![false; animations.len()];
+        for group in groups(animations) {
+            for index in group.clone() {
+                let target = animations[index].target.index();
+                let written = animations[index + 1..group.end]
+                    .iter()
+                    .filter(|later| later.target.index() == target)
+                    .fold(0, |written, later| written | overwrites(&later.effect));
+                let writes = writes(&animations[index].effect);
+                if writes & !written == 0 && !firsts.contains(&(trigger, index)) {
+                    dead[index] = true;
+                    removed.push(Removal::Animation { trigger, index });
+                }
+            }
+        }
+        remove(animations, &dead);
+    }
+    removed
+}
+
+fn fold_paths(timeline: &mut Timeline) -> Vec<Removal> {
+    let mut removed = Vec::new();
+    for (trigger, context) in sequences_mut(timeline) {
+        let animations = &mut context.animations;
+        let mut dead = vec![false; animations.len()];
+        for group in groups(animations) {
+            for index in group.clone() {
+                if dead[index] || !matches!(animations[index].effect, Effect::Path { .. }) {
+                    continue;
+                }
+                let target = animations[index].target.index();
+                for next in index + 1..group.end {
+                    if animations[next].target.index() != target {
+                        continue;
+                    }
+                    let Effect::Path {
+                        path,
+                        x,
+                        y,
+                        relative,
+                    } = animations[next].effect.clone()
+                    else {
+                        break;
+                    };
+                    // the fold stays in place of the first path, which may
+                    // be the first effect of the shape
+                    if let Effect::Path {
+                        path: folded,
+                        x: fx,
+                        y: fy,
+                        relative: frelative,
+                    } = &mut animations[index].effect
+                    {
+                        folded.extend(path);
+                        (*fx, *fy, *frelative) = (x, y, relative);
+                    }
+                    dead[next] = true;
+                    removed.push(Removal::Animation {
+                        trigger,
+                        index: next,
+                    });
+                }
+            }
+        }
+        remove(animations, &dead);
+    }
+    removed
+}
+
+fn dead_contexts(timeline: &mut Timeline, shapes: &[(usize, Shape)]) -> Vec<Removal> {
+    let positions = lint::positions(shapes, timeline);
+    let firsts = firsts(timeline);
+    let mut removed = Vec::new();
+    for (trigger, _) in lint::covered_triggers(shapes, timeline, &positions) {
+        let animations = &mut timeline.contexts[trigger].animations;
+        let dead = (0..animations.len())
+            .map(|index| !firsts.contains(&(Some(trigger), index)))
+            .collect::<Vec<_>>();
+        let count = dead.iter().filter(|dead| **dead).count();
+        if count > 0 {
+            remove(animations, &dead);
+            removed.push(Removal::Context {
+                trigger,
+                animations: count,
+            });
+        }
+    }
+    removed
+}
+
+// Whether playing b after a ends in the same state as the other way round
+fn commute(a: &[Animation], b: &[Animation]) -> bool {
+    a.iter().all(|a| {
+        b.iter().all(|b| {
+            a.target.index() != b.target.index() || writes(&a.effect) & writes(&b.effect) == 0
+        })
+    })
+}
+
+fn merge_groups(timeline: &mut Timeline) -> Vec<Removal> {
+    let mut removed = Vec::new();
+    for (trigger, context) in sequences_mut(timeline) {
+        let animations = &mut context.animations;
+        let groups = groups(animations);
+        let Some(mut merged) = groups.first().cloned() else {
+            continue;
+        };
+        for (group, range) in groups.into_iter().enumerate().skip(1) {
+            if commute(&animations[merged.clone()], &animations[range.clone()]) {
+                animations[range.start].click = false;
+                merged.end = range.end;
+                removed.push(Removal::Group { trigger, group });
+            } else {
+                merged = range;
+            }
+        }
+    }
+    removed
+}
+
+// Runs the passes in order over the timeline of the shapes, reporting what
+// each one removed
+pub fn run(timeline: &mut Timeline, shapes: &[(usize, Shape)], passes: &[Pass]) -> Vec<Report> {
+    passes
+        .iter()
+        .map(|&pass| {
+            let removed = match pass {
+                Pass::Overwritten => overwritten(timeline),
+                Pass::DeadContexts => dead_contexts(timeline, shapes),
+                Pass::FoldPaths => fold_paths(timeline),
+                Pass::MergeGroups => merge_groups(timeline),
+            };
+            Report { pass, removed }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runners::{basic, minimal, reference, Config, Runner, ShowEnd},
+        z, MacroEffect, Slide,
+    };
+
+    type Minimal = minimal::Presentation;
+    type Basic = basic::Presentation;
+    type Reference = reference::Presentation;
+
+    // Whether the runner plays the script the same on both slides
+    fn same<R: Runner>(
+        slide: &Slide,
+        optimised: &Slide,
+        config: Config,
+        script: &[(f32, f32)],
+    ) -> bool {
+        let mut original = R::build(slide.clone(), config).unwrap();
+        let mut optimised = R::build(optimised.clone(), config).unwrap();
+        script.iter().all(|&(x, y)| {
+            original.click(x, y).unwrap();
+            optimised.click(x, y).unwrap();
+            optimised.shapes() == original.shapes()
+        })
+    }
+
+    // Every pass has something to remove, the safe ones leave the show as it was
+    // in every runner
+    #[test]
+    fn passes() {
+        let mut slide = Slide::new(40., 30.);
+        let a = slide.rect(2., 2., 4., 4.).z(z!(3)).handle();
+        let b = slide.rect(20., 2., 4., 4.).z(z!(4)).handle();
+        // covers the trigger, which can never be clicked
+        slide.rect(10., 10., 10., 10.).z(z!(1));
+        let trigger = slide.rect(12., 12., 4., 4.).z(z!(2)).handle();
+        slide
+            .main()
+            .animate(a, MacroEffect::Path(5., 0.))
+            .animate(b, MacroEffect::Disappear)
+            .animate(a, MacroEffect::Disappear)
+            .after_click()
+            .animate(a, MacroEffect::Appear)
+            .animate(a, MacroEffect::Path(1., 0.))
+            .animate(a, MacroEffect::Path(2., 0.))
+            .animate(b, MacroEffect::Appear)
+            .after_click();
+        slide.on(trigger).animate(b, MacroEffect::Appear);
+
+        let mut optimised = slide.clone();
+        let reports = optimised.optimise(&Pass::SAFE);
+        assert_eq!(
+            reports[0].removed,
+            [Removal::Animation {
+                trigger: None,
+                index: 2
+            }]
+        );
+        let reports = optimised.optimise(&[Pass::DeadContexts]);
+        assert_eq!(
+            reports[0].removed,
+            [Removal::Context {
+                trigger: 3,
+                animations: 1
+            }]
+        );
+        assert_eq!(optimised.timeline.animations().count(), 6);
+        let config = Config {
+            show_end: ShowEnd::Wrap,
+            ..Config::default()
+        };
+        let script = [(13., 13.), (3., 3.), (8., 3.), (21., 3.)].repeat(5);
+        assert!(same::<Reference>(&slide, &optimised, config, &script));
+        assert!(same::<Basic>(&slide, &optimised, config, &script));
+        assert!(same::<Minimal>(&slide, &optimised, config, &script));
+
+        // the paths of a fold into the last one, which the reference runner plays
+        // from where the first one left the shape
+        let mut folded = optimised.clone();
+        let reports = folded.optimise(&[Pass::FoldPaths]);
+        assert_eq!(
+            reports[0].removed,
+            [Removal::Animation {
+                trigger: None,
+                index: 4
+            }]
+        );
+        assert!(!same::<Reference>(&slide, &folded, config, &script));
+        assert!(same::<Basic>(&slide, &folded, config, &script));
+        assert!(same::<Minimal>(&slide, &folded, config, &script));
+
+        // the last click group only touches b, it joins the previous one
+        let reports = optimised.optimise(&[Pass::MergeGroups]);
+        assert_eq!(
+            reports[0].removed,
+            [Removal::Group {
+                trigger: None,
+                group: 2
+            }]
+        );
+
+        // a trigger under the cover at every position lint finds for it, the
+        // reference runner adding up its relative paths takes it out and it
+        // hides then shows the other shape
+        let mut slide = Slide::new(40., 30.);
+        let other = slide.rect(2., 2., 4., 4.).z(z!(3)).handle();
+        slide.rect(10., 10., 20., 20.).z(z!(1));
+        let trigger = slide.rect(12., 12., 4., 4.).z(z!(2)).handle();
+        slide
+            .main()
+            .animate(trigger, MacroEffect::Path(5., 0.))
+            .after_click();
+        slide
+            .on(trigger)
+            .animate(other, MacroEffect::Disappear)
+            .after_click()
+            .animate(other, MacroEffect::Appear)
+            .after_click();
+        let script = [(1., 1.); 4]
+            .into_iter()
+            .chain([(33., 13.); 2])
+            .collect::<Vec<_>>();
+        let mut dead = slide.clone();
+        let reports = dead.optimise(&[Pass::DeadContexts]);
+        assert_eq!(
+            reports[0].removed,
+            [Removal::Context {
+                trigger: 2,
+                animations: 1
+            }]
+        );
+        assert!(!same::<Reference>(&slide, &dead, config, &script));
+        assert!(same::<Basic>(&slide, &dead, config, &script));
+        assert!(same::<Minimal>(&slide, &dead, config, &script));
+        let mut safe = slide.clone();
+        safe.optimise(&Pass::SAFE);
+        assert!(same::<Reference>(&slide, &safe, config, &script));
+    }
+}
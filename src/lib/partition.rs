@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use crate::{filters::Filter, lint, runners::reference::flatten, Slide};
+
+type Rect = (f32, f32, f32, f32);
+
+// Rectangles each flattened shape can occupy, in the order the runners lay
+// them out: its home and the motion path destinations
+pub fn rects(slide: &Slide) -> Vec<Vec<Rect>> {
+    let positions = lint::positions(&slide.shapes, &slide.timeline);
+    let mut order = (0..slide.shapes.len()).collect::<Vec<_>>();
+    order.sort_by_key(|id| slide.shapes[*id].1.z());
+    let mut rects = Vec::new();
+    for id in order.into_iter().rev() {
+        let shape = &slide.shapes[id].1;
+        let (hx, hy, _, _) = shape.bounds();
+        let mut members = Vec::new();
+        flatten(shape.clone(), &mut members);
+        for (_, state) in members {
+            rects.push(
+                positions[id]
+                    .iter()
+                    .map(|&(x, y)| (state.x + x - hx, state.y + y - hy, state.w, state.h))
+                    .collect(),
+            );
+        }
+    }
+    rects
+}
+
+// Lookup key of a coordinate: edges get their own slot since shapes contain
+// their edges, the open intervals between them get the others
+fn slot(edges: &[f32], v: f32) -> usize {
+    match edges.binary_search_by(|edge| edge.total_cmp(&v)) {
+        Ok(index) => 2 * index + 1,
+        Err(index) => 2 * index,
+    }
+}
+
+// Coordinate standing for every point of a slot
+fn sample(edges: &[f32], slot: usize) -> f32 {
+    let index = slot / 2;
+    if slot % 2 == 1 {
+        edges[index]
+    } else if index == 0 {
+        edges[0] - 1.
+    } else if index == edges.len() {
+        edges[index - 1] + 1.
+    } else {
+        (edges[index - 1] + edges[index]) / 2.
+    }
+}
+
+fn edges(rects: &[Vec<Rect>], limit: f32, start: fn(&Rect) -> (f32, f32)) -> Vec<f32> {
+    let mut edges = vec![0., limit];
+    for &rect in rects.iter().flatten() {
+        let (v, size) = start(&rect);
+        edges.extend(
+            [v, v + size]
+                .into_iter()
+                .filter(|v| *v >= 0. && *v <= limit),
+        );
+    }
+    edges.sort_by(f32::total_cmp);
+    edges.dedup();
+    edges
+}
+
+// The slide cut along every edge a shape can have, so a single set of shapes
+// may cover each region. Regions sharing the same set share its bitset.
+#[derive(Clone, Debug)]
+pub struct Partition {
+    xs: Vec<f32>,
+    ys: Vec<f32>,
+    rows: usize,
+    regions: Vec<usize>,
+    sets: Vec<Filter>,
+}
+
+impl Partition {
+    pub fn new(width: f32, height: f32, rects: &[Vec<Rect>]) -> Self {
+        let xs = edges(rects, width, |&(x, _, w, _)| (x, w));
+        let ys = edges(rects, height, |&(_, y, _, h)| (y, h));
+        let (columns, rows) = (2 * xs.len() + 1, 2 * ys.len() + 1);
+        let mut ids = HashMap::new();
+        let mut sets = Vec::new();
+        let mut regions = Vec::with_capacity(columns * rows);
+        for column in 0..columns {
+            let x = sample(&xs, column);
+            let crossing = rects
+                .iter()
+                .enumerate()
+                .flat_map(|(index, rects)| rects.iter().map(move |rect| (index, rect)))
+                .filter(|(_, &(rx, _, w, _))| x >= rx && x <= rx + w)
+                .collect::<Vec<_>>();
+            for row in 0..rows {
+                let y = sample(&ys, row);
+                let mut set = crossing
+                    .iter()
+                    .filter(|(_, &(_, ry, _, h))| y >= ry && y <= ry + h)
+                    .map(|(index, _)| *index)
+                    .collect::<Vec<_>>();
+                set.dedup();
+                let id = *ids.entry(set).or_insert_with_key(|set| {
+                    let mut filter = Filter::new(rects.len());
+                    for &index in set {
+                        filter.set(index);
+                    }
+                    sets.push(filter);
+                    sets.len() - 1
+                });
+                regions.push(id);
+            }
+        }
+        Self {
+            xs,
+            ys,
+            rows,
+            regions,
+            sets,
+        }
+    }
+
+    pub fn from_slide(slide: &Slide) -> Self {
+        Self::new(slide.width, slide.height, &rects(slide))
+    }
+
+    pub fn regions(&self) -> usize {
+        self.sets.len()
+    }
+
+    // Shapes that may contain the point
+    pub fn candidates(&self, x: f32, y: f32) -> &Filter {
+        let region = slot(&self.xs, x) * self.rows + slot(&self.ys, y);
+        &self.sets[self.regions[region]]
+    }
+}
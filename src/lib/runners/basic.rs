@@ -1,8 +1,17 @@
-use std::{cmp::Reverse, fmt, process::exit};
+use std::{cmp::Reverse, collections::HashSet, fmt, mem::size_of, sync::Arc, time::Instant};
 
+use super::{
+    profile::Profile, CompileReport, Config, HitTest, Outcome, Runner, RunnerKind, ShapeView,
+    ShowEnd, Trigger,
+};
 use crate::{
-    filters::Filter, render::Canvas, Color, Context, Effect, Preset, Referer, Shape, ShapeState,
-    Slide, Timeline,
+    error::{Error, Result},
+    filters::{Filter, CELL_SHIFT},
+    grid::Grid,
+    hash, in_bounds,
+    partition::Partition,
+    render::Canvas,
+    Animation, Color, Context, Effect, Preset, Referer, Shape, ShapeState, Slide, Timeline,
 };
 
 #[derive(Clone, Copy)]
@@ -44,6 +53,15 @@ impl ShapeDynState {
     pub fn is_visible(&self) -> bool {
         self.visibility.is_visible()
     }
+
+    pub fn rect(&self) -> (f32, f32, f32, f32) {
+        (self.x, self.y, self.w, self.h)
+    }
+
+    pub fn key(&self, index: usize) -> u64 {
+        hash::key(hash::SHAPE, index, hash::point(self.x, self.y))
+            ^ hash::key(hash::VISIBLE, index, self.is_visible() as u64)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -61,37 +79,103 @@ pub struct CacheData {
     pub end: usize,
 }
 
-pub struct Presentation {
-    pub states_dyn: Vec<ShapeDynState>,
+// Everything fixed once the slide is compiled, shared by the machines playing it
+pub struct Program {
     pub states_const: Vec<ShapeConstState>,
     pub referers: Vec<Referer>,
+    pub names: Vec<&'static str>,
+    // the heads of the contexts are kept by the machine state
     pub timeline: Timeline,
-    pub cache_hit: CacheHit,
-    pub cache_data: CacheData,
     pub width: f32,
     pub height: f32,
+    pub partition: Option<Partition>,
+    pub hit_test: HitTest,
+    pub show_end: ShowEnd,
+    pub report: CompileReport,
+}
+
+#[derive(Clone)]
+pub struct State {
+    pub states_dyn: Vec<ShapeDynState>,
+    // head of each shape context, then of the main one
+    pub heads: Vec<usize>,
+    pub cache_hit: CacheHit,
+    pub cache_data: CacheData,
     pub iters: usize,
     pub filter: Filter,
+    // built on the first grid hit test
+    pub grid: Option<Grid>,
+    // hash of the shape states and context heads, kept up to date by click
+    pub hash: u64,
+}
+
+// A machine playing a program, cloning it only copies the state
+#[derive(Clone)]
+pub struct Presentation {
+    pub program: Arc<Program>,
+    pub state: State,
+    pub profile: Option<Profile>,
 }
 
-impl From<Slide> for Presentation {
-    fn from(mut slide: Slide) -> Self {
-        slide.shapes.sort_by(|a, b| a.1.z().cmp(&b.1.z()));
-        let total_size = slide.shapes.iter().map(|e| e.1.size()).sum();
-        let mut refs = vec![Referer::Shape(0); slide.shapes.len()];
-        let mut shapes_dyn = Vec::with_capacity(total_size);
-        let mut shapes_const = Vec::with_capacity(total_size);
-        let mut shapes_groups = Vec::with_capacity(total_size);
-        let mut referer_id = 0;
-        for (id, shape) in slide.shapes.into_iter().rev() {
-            let group_size = shape.size();
-            let (mut queue, referer) = match shape {
+fn compile(mut slide: Slide) -> Result<(Program, State)> {
+    slide.check_referers()?;
+    slide.shapes.sort_by_key(|a| a.1.z());
+    let total_size = slide.shapes.iter().map(|e| e.1.size()).sum();
+    let mut report = CompileReport {
+        shapes: slide.shapes.len(),
+        flattened: total_size,
+        contexts: slide
+            .timeline
+            .contexts
+            .iter()
+            .filter(|c| !c.animations.is_empty())
+            .count(),
+        animations: slide.timeline.animations().count(),
+        ..CompileReport::default()
+    };
+    let mut refs = vec![Referer::Shape(0); slide.shapes.len()];
+    let mut shapes_dyn = Vec::with_capacity(total_size);
+    let mut shapes_const = Vec::with_capacity(total_size);
+    let mut shapes_groups = Vec::with_capacity(total_size);
+    let mut names = Vec::with_capacity(total_size);
+    let mut referer_id = 0;
+    for (id, shape) in slide.shapes.into_iter().rev() {
+        let group_size = shape.size();
+        let (mut queue, referer) = match shape {
+            Shape::Shape {
+                name,
+                state: ShapeState { x, y, w, h, color },
+                ..
+            } => {
+                let referer = Referer::Shape(referer_id);
+                refs[id] = referer;
+                shapes_dyn.push(ShapeDynState {
+                    x,
+                    y,
+                    w,
+                    h,
+                    visibility: Visibility::Unknown,
+                });
+                shapes_const.push(ShapeConstState { color, x, y });
+                shapes_groups.push(referer);
+                names.push(name);
+                referer_id += 1;
+                continue;
+            }
+            Shape::Group { mut shapes, .. } => {
+                let referer = Referer::Group(referer_id, group_size);
+                shapes.sort_by_key(|a| Reverse(a.z()));
+                refs[id] = referer;
+                (shapes, referer)
+            }
+        };
+        while let Some(shape) = queue.pop() {
+            match shape {
                 Shape::Shape {
+                    name,
                     state: ShapeState { x, y, w, h, color },
                     ..
                 } => {
-                    let referer = Referer::Shape(referer_id);
-                    refs[id] = referer;
                     shapes_dyn.push(ShapeDynState {
                         x,
                         y,
@@ -101,72 +185,118 @@ impl From<Slide> for Presentation {
                     });
                     shapes_const.push(ShapeConstState { color, x, y });
                     shapes_groups.push(referer);
+                    names.push(name);
                     referer_id += 1;
-                    continue;
                 }
                 Shape::Group { mut shapes, .. } => {
-                    let referer = Referer::Group(referer_id, group_size);
                     shapes.sort_by_key(|a| Reverse(a.z()));
-                    refs[id] = referer;
-                    (shapes, referer)
-                }
-            };
-            while let Some(shape) = queue.pop() {
-                match shape {
-                    Shape::Shape {
-                        state: ShapeState { x, y, w, h, color },
-                        ..
-                    } => {
-                        shapes_dyn.push(ShapeDynState {
-                            x,
-                            y,
-                            w,
-                            h,
-                            visibility: Visibility::Unknown,
-                        });
-                        shapes_const.push(ShapeConstState { color, x, y });
-                        shapes_groups.push(referer);
-                        referer_id += 1;
-                    }
-                    Shape::Group { mut shapes, .. } => {
-                        shapes.sort_by_key(|a| Reverse(a.z()));
-                        queue = [queue, shapes].concat();
-                    }
+                    queue = [queue, shapes].concat();
                 }
             }
         }
+    }
 
-        let mut main_context = slide.timeline.main_context;
-        init_context(&mut main_context, &refs, &mut shapes_dyn, &mut shapes_const);
-        let mut contexts = vec![Context::default(); total_size];
-        for (id, mut context) in slide.timeline.contexts.into_iter().enumerate() {
-            init_context(&mut context, &refs, &mut shapes_dyn, &mut shapes_const);
-            contexts[refs[id].index()] = context;
+    let mut main_context = slide.timeline.main_context;
+    init_context(&mut main_context, &refs, &mut shapes_dyn, &mut shapes_const)?;
+    let mut contexts = vec![Context::default(); total_size];
+    for (id, mut context) in slide.timeline.contexts.into_iter().enumerate() {
+        if context.animations.is_empty() {
+            continue;
         }
+        let referer = refs.get(id).ok_or(Error::DanglingReferer {
+            referer: id,
+            shapes: refs.len(),
+        })?;
+        init_context(&mut context, &refs, &mut shapes_dyn, &mut shapes_const)?;
+        contexts[referer.index()] = context;
+    }
 
-        Presentation {
-            timeline: Timeline {
-                main_context,
-                contexts,
-            },
-            cache_hit: CacheHit {
-                x: 0.,
-                y: 0.,
-                index: 0,
-            },
-            cache_data: CacheData {
-                update: false,
-                start: 0,
-                end: shapes_dyn.len(),
-            },
-            states_dyn: shapes_dyn,
-            states_const: shapes_const,
-            referers: shapes_groups,
-            width: slide.width,
-            height: slide.height,
-            iters: 0,
-            filter: Filter::new(total_size),
-        }
+    let timeline = Timeline {
+        main_context,
+        contexts,
+    };
+    // the animations are played as they are, one click group after the other
+    report.merged = timeline.animations().count();
+    let contexts = timeline.contexts.iter().chain([&timeline.main_context]);
+    report.groups = contexts
+        .map(|context| groups(&context.animations))
+        .collect();
+    let cells = timeline.animations().flat_map(|animation| {
+        let (start, end) = animation.target.bounds();
+        (start..end).map(|i| i >> CELL_SHIFT)
+    });
+    report.cells = cells.collect::<HashSet<_>>().len();
+    let mut program = Program {
+        timeline,
+        states_const: shapes_const,
+        referers: shapes_groups,
+        names,
+        width: slide.width,
+        height: slide.height,
+        partition: None,
+        hit_test: HitTest::default(),
+        show_end: ShowEnd::default(),
+        report,
+    };
+    let state = State {
+        // no point is cached until the first hit test
+        cache_hit: CacheHit {
+            x: f32::NAN,
+            y: f32::NAN,
+            index: 0,
+        },
+        cache_data: CacheData {
+            update: false,
+            start: 0,
+            end: shapes_dyn.len(),
+        },
+        states_dyn: shapes_dyn,
+        heads: vec![0; total_size + 1],
+        iters: 0,
+        filter: Filter::new(total_size),
+        grid: None,
+        hash: 0,
+    };
+    program.report.memory = memory(&program, &state);
+    Ok((program, state))
+}
+
+// Click groups of a context, the click flag of its first animation is ignored
+fn groups(animations: &[Animation]) -> usize {
+    match animations.split_first() {
+        Some((_, rest)) => 1 + rest.iter().filter(|animation| animation.click).count(),
+        None => 0,
+    }
+}
+
+// Bytes held by the program and the state, the partition and grid aside
+fn memory(program: &Program, state: &State) -> usize {
+    let timeline = &program.timeline;
+    let contexts = timeline.contexts.iter().chain([&timeline.main_context]);
+    let animations = contexts.clone().flat_map(|context| &context.animations);
+    let paths = animations.clone().map(|animation| match &animation.effect {
+        Effect::Path { path, .. } => path.len() * size_of::<(f32, f32)>(),
+        _ => 0,
+    });
+    size_of::<Program>()
+        + size_of::<State>()
+        + contexts.count() * size_of::<Context>()
+        + animations.count() * size_of::<Animation>()
+        + paths.sum::<usize>()
+        + program.states_const.len() * size_of::<ShapeConstState>()
+        + program.referers.len() * size_of::<Referer>()
+        + program.names.len() * size_of::<&str>()
+        + state.states_dyn.len() * size_of::<ShapeDynState>()
+        + state.heads.len() * size_of::<usize>()
+        + state.filter.cells.len() * size_of::<usize>()
+}
+
+impl TryFrom<Slide> for Presentation {
+    type Error = Error;
+
+    fn try_from(slide: Slide) -> Result<Self> {
+        let (program, state) = compile(slide)?;
+        Ok(Presentation::new(Arc::new(program), state))
     }
 }
 
@@ -174,9 +304,9 @@ impl fmt::Debug for Presentation {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!(
             "cache_hit: {:?}\ncache_data: {:?}\n",
-            self.cache_hit, self.cache_data,
+            self.state.cache_hit, self.state.cache_data,
         ))?;
-        for state_dyn in &self.states_dyn {
+        for state_dyn in &self.state.states_dyn {
             match state_dyn.visibility {
                 Visibility::Visible => f.write_str("|=====================")?,
                 Visibility::Hidden => f.write_str("|                     ")?,
@@ -184,16 +314,18 @@ impl fmt::Debug for Presentation {
             }
         }
         f.write_str("|\n")?;
-        for state_dyn in &self.states_dyn {
+        for state_dyn in &self.state.states_dyn {
             f.write_fmt(format_args!(
                 "| {:04} {:04} {:04} {:04} ",
                 state_dyn.x, state_dyn.y, state_dyn.w, state_dyn.h
             ))?;
         }
         f.write_str("|\n")?;
-        for referer in &self.referers {
+        for referer in &self.program.referers {
             match referer {
-                Referer::Shape(index) => f.write_fmt(format_args!("| Shape({index:08})     "))?,
+                Referer::Shape(index) | Referer::Slide(_, index) => {
+                    f.write_fmt(format_args!("| Shape({index:08})     "))?
+                }
                 Referer::Group(index, size) => {
                     f.write_fmt(format_args!("| Group({index:08}, {size:02}) "))?
                 }
@@ -205,190 +337,208 @@ impl fmt::Debug for Presentation {
 }
 
 impl Presentation {
-    pub fn under(&mut self, x: f32, y: f32) -> Option<Referer> {
-        for (index, state) in self.states_dyn.iter().enumerate().rev() {
-            if state.is_visible() && state.contains(x, y) {
-                self.iters += self.states_dyn.len() - index;
-                return Some(self.referers[index]);
+    // Machine playing the program from the state, hashed from scratch
+    pub fn new(program: Arc<Program>, state: State) -> Self {
+        let mut presentation = Self {
+            program,
+            state,
+            profile: None,
+        };
+        presentation.state.hash = presentation.rehash();
+        presentation
+    }
+
+    pub fn on_slide(&self, x: f32, y: f32) -> bool {
+        in_bounds(x, y, self.program.width, self.program.height)
+    }
+
+    // Flattened index of the topmost visible shape under the point, the hit
+    // tests below find the same one
+    pub fn under(&mut self, x: f32, y: f32) -> Option<usize> {
+        if !self.on_slide(x, y) {
+            return None;
+        }
+        let state = &mut self.state;
+        for (index, state_dyn) in state.states_dyn.iter().enumerate().rev() {
+            if state_dyn.is_visible() && state_dyn.contains(x, y) {
+                state.iters += state.states_dyn.len() - index;
+                return Some(index);
             }
         }
-        self.iters += self.states_dyn.len();
+        state.iters += state.states_dyn.len();
         None
     }
 
-    pub fn under_cache(&mut self, x: f32, y: f32) -> Option<Referer> {
-        let start_index = if self.cache_hit.x == x && self.cache_hit.y == y {
-            self.cache_hit.index + 1
+    pub fn under_cache(&mut self, x: f32, y: f32) -> Option<usize> {
+        if !self.on_slide(x, y) {
+            return None;
+        }
+        let state = &mut self.state;
+        let start_index = if state.cache_hit.x == x && state.cache_hit.y == y {
+            state.cache_hit.index + 1
         } else {
-            self.states_dyn.len()
+            state.states_dyn.len()
         };
-        for (index, state) in self.states_dyn.iter().enumerate().take(start_index).rev() {
-            if state.is_visible() && state.contains(x, y) {
-                self.iters += start_index - index;
-                self.cache_hit = CacheHit { x, y, index };
-                return Some(self.referers[index]);
+        for (index, state_dyn) in state.states_dyn.iter().enumerate().take(start_index).rev() {
+            if state_dyn.is_visible() && state_dyn.contains(x, y) {
+                state.iters += start_index - index;
+                state.cache_hit = CacheHit { x, y, index };
+                return Some(index);
             }
         }
-        self.iters += start_index;
-        self.cache_hit = CacheHit { x, y, index: 0 };
+        state.iters += start_index;
+        state.cache_hit = CacheHit { x, y, index: 0 };
         None
     }
 
-    pub fn under_filter(&mut self, x: f32, y: f32) -> Option<Referer> {
-        if self.cache_hit.x != x || self.cache_hit.y != y {
-            return self.under(x, y);
+    pub fn under_filter(&mut self, x: f32, y: f32) -> Option<usize> {
+        if !self.on_slide(x, y) {
+            return None;
         }
-        self.filter.last().map(|index| self.referers[index])
-    }
-
-    pub fn click(&mut self, x: f32, y: f32) {
-        self.cache_data.update = true;
-        self.cache_data.start = 0;
-        self.cache_data.end = self.states_dyn.len() - 1;
-        let (target, context) = match self.under(x, y) {
-            Some(referer) => {
-                let context = &mut self.timeline.contexts[referer.index()];
-                if context.animations.is_empty() {
-                    (None, &mut self.timeline.main_context)
-                } else {
-                    (Some(referer), context)
-                }
-            }
-            None => (None, &mut self.timeline.main_context),
-        };
-        let mut first = true;
-        let head = if context.head == context.animations.len() {
-            if target.is_none() {
-                exit(1);
-            }
-            0
-        } else {
-            context.head
-        };
-        context.head = context.animations.len();
-        for (head, animation) in context.animations.iter_mut().enumerate().skip(head) {
-            if !first && animation.click {
-                context.head = head;
-                break;
-            }
-            first = false;
-            let (start, end) = animation.target.bounds();
-            for i in start..end {
-                apply_effect(
-                    &mut animation.effect,
-                    &mut self.states_dyn[i],
-                    &self.states_const[i],
-                );
-            }
+        if self.state.cache_hit.x != x || self.state.cache_hit.y != y {
+            self.update_filter(x, y);
         }
+        let last = self.state.filter.last();
+        let cells = self.state.filter.cells();
+        self.state.iters += last.map_or(cells, |index| cells - (index >> CELL_SHIFT));
+        last
     }
 
-    pub fn click_cache(&mut self, x: f32, y: f32) {
-        let (target, context) = match self.under_cache(x, y) {
-            Some(referer) => {
-                let context = &mut self.timeline.contexts[referer.index()];
-                if context.animations.is_empty() {
-                    (None, &mut self.timeline.main_context)
-                } else {
-                    (Some(referer), context)
-                }
-            }
-            None => (None, &mut self.timeline.main_context),
-        };
-        let mut first = true;
-        let head = if context.head == context.animations.len() {
-            if target.is_none() {
-                exit(1);
+    pub fn under_grid(&mut self, x: f32, y: f32) -> Option<usize> {
+        if !self.on_slide(x, y) {
+            return None;
+        }
+        let (program, state) = (&*self.program, &mut self.state);
+        let states_dyn = &state.states_dyn;
+        let grid = state
+            .grid
+            .get_or_insert_with(|| index(states_dyn, program.width, program.height));
+        let candidates = grid.candidates(x, y);
+        for (checked, &index) in candidates.iter().rev().enumerate() {
+            let state_dyn = &states_dyn[index];
+            if state_dyn.is_visible() && state_dyn.contains(x, y) {
+                state.iters += checked + 1;
+                return Some(index);
             }
-            0
-        } else {
-            context.head
-        };
-        let mut cache_index = self.cache_hit.index;
-        let (mut cache_min, mut cache_max) = if self.cache_data.update {
-            (self.cache_data.start, self.cache_data.end)
-        } else {
-            (self.states_dyn.len(), 0)
+        }
+        state.iters += candidates.len();
+        None
+    }
+
+    pub fn under_partition(&mut self, x: f32, y: f32) -> Option<usize> {
+        let Some(partition) = &self.program.partition else {
+            return self.under(x, y);
         };
-        context.head = context.animations.len();
-        for (head, animation) in context.animations.iter_mut().enumerate().skip(head) {
-            if !first && animation.click {
-                context.head = head;
-                break;
-            }
-            first = false;
-            let (start, end) = animation.target.bounds();
-            for i in start..end {
-                let (perceptible, obstructible) = apply_effect(
-                    &mut animation.effect,
-                    &mut self.states_dyn[i],
-                    &self.states_const[i],
-                );
-                if obstructible
-                    && i > cache_index
-                    && self.states_dyn[i].contains(self.cache_hit.x, self.cache_hit.y)
-                {
-                    cache_index = i;
-                }
-                if perceptible && i < cache_min {
-                    cache_min = i;
-                }
-                if perceptible && i > cache_max {
-                    cache_max = i;
-                }
+        if !self.on_slide(x, y) {
+            return None;
+        }
+        for index in partition.candidates(x, y).rev_iter() {
+            self.state.iters += 1;
+            let state_dyn = &self.state.states_dyn[index];
+            if state_dyn.is_visible() && state_dyn.contains(x, y) {
+                return Some(index);
             }
         }
-        self.cache_hit.index = cache_index;
-        self.cache_data.start = cache_min;
-        self.cache_data.end = cache_max;
-        self.cache_data.update = true;
-    }
-
-    pub fn click_filter(&mut self, x: f32, y: f32) {
-        let (target, context) = match self.under_filter(x, y) {
-            Some(referer) => {
-                let context = &mut self.timeline.contexts[referer.index()];
-                if context.animations.is_empty() {
-                    (None, &mut self.timeline.main_context)
-                } else {
-                    (Some(referer), context)
-                }
+        None
+    }
+
+    pub fn hit(&mut self, x: f32, y: f32) -> Option<usize> {
+        match self.program.hit_test {
+            HitTest::Scan => self.under(x, y),
+            HitTest::Cache => self.under_cache(x, y),
+            HitTest::Filter => self.under_filter(x, y),
+            HitTest::Grid => self.under_grid(x, y),
+            HitTest::Partition => self.under_partition(x, y),
+        }
+    }
+
+    // Hashes the whole state from scratch
+    pub fn rehash(&self) -> u64 {
+        let shapes = self.state.states_dyn.iter().enumerate();
+        let heads = self.state.heads.iter().enumerate();
+        shapes.fold(0, |hash, (i, state)| hash ^ state.key(i))
+            ^ heads.fold(0, |hash, (i, head)| {
+                hash ^ hash::key(hash::HEAD, i, *head as u64)
+            })
+    }
+
+    pub fn click(&mut self, x: f32, y: f32) -> Result<Outcome> {
+        if !self.on_slide(x, y) {
+            return Ok(Outcome::Missed);
+        }
+        let iters = self.state.iters;
+        let target = self.hit(x, y).map(|index| self.program.referers[index]);
+        let start = self.profile.is_some().then(Instant::now);
+        let (program, state) = (&*self.program, &mut self.state);
+        let contexts = &program.timeline.contexts;
+        let main = contexts.len();
+        let (target, context) = match target {
+            Some(referer) if !contexts[referer.index()].animations.is_empty() => {
+                (Some(referer), &contexts[referer.index()])
             }
-            None => (None, &mut self.timeline.main_context),
+            _ => (None, &program.timeline.main_context),
         };
+        let index = target.map_or(main, |referer| referer.index());
         let mut first = true;
-        let head = if context.head == context.animations.len() {
-            if target.is_none() {
-                exit(1);
+        let mut animations = 0;
+        let old_head = state.heads[index];
+        let head = if old_head == context.animations.len() {
+            if target.is_none() && program.show_end == ShowEnd::Stop {
+                return Ok(Outcome::EndOfShow);
             }
             0
         } else {
-            context.head
+            old_head
         };
-        let (mut cache_min, mut cache_max) = if self.cache_data.update {
-            (self.cache_data.start, self.cache_data.end)
+        let (cx, cy) = (state.cache_hit.x, state.cache_hit.y);
+        let mut cache_index = state.cache_hit.index;
+        let (mut cache_min, mut cache_max) = if state.cache_data.update {
+            (state.cache_data.start, state.cache_data.end)
         } else {
-            (self.states_dyn.len(), 0)
+            (state.states_dyn.len(), 0)
         };
-        context.head = context.animations.len();
-        for (head, animation) in context.animations.iter_mut().enumerate().skip(head) {
+        let mut next = context.animations.len();
+        for (head, animation) in context.animations.iter().enumerate().skip(head) {
             if !first && animation.click {
-                context.head = head;
+                next = head;
                 break;
             }
             first = false;
+            animations += 1;
             let (start, end) = animation.target.bounds();
             for i in start..end {
+                let rect = state.states_dyn[i].rect();
+                let key = state.states_dyn[i].key(i);
                 let (perceptible, obstructible) = apply_effect(
-                    &mut animation.effect,
-                    &mut self.states_dyn[i],
-                    &self.states_const[i],
+                    &animation.effect,
+                    &mut state.states_dyn[i],
+                    &program.states_const[i],
                 );
-                let state = &self.states_dyn[i];
-                if obstructible && state.contains(self.cache_hit.x, self.cache_hit.y) {
-                    self.filter.set(i);
-                } else {
-                    self.filter.unset(i);
+                state.hash ^= key ^ state.states_dyn[i].key(i);
+                // keep the strategy's view of the cached point up to date
+                let covering = obstructible && state.states_dyn[i].contains(cx, cy);
+                match program.hit_test {
+                    HitTest::Scan => {}
+                    HitTest::Cache => {
+                        if covering && i > cache_index {
+                            cache_index = i;
+                        }
+                    }
+                    HitTest::Filter => {
+                        if covering {
+                            state.filter.set(i);
+                        } else {
+                            state.filter.unset(i);
+                        }
+                    }
+                    HitTest::Grid => {
+                        let moved = state.states_dyn[i].rect();
+                        if let Some(grid) = state.grid.as_mut().filter(|_| moved != rect) {
+                            grid.remove(i, rect);
+                            grid.insert(i, moved);
+                        }
+                    }
+                    HitTest::Partition => {}
                 }
                 if perceptible && i < cache_min {
                     cache_min = i;
@@ -398,37 +548,62 @@ impl Presentation {
                 }
             }
         }
-        self.cache_data.start = cache_min;
-        self.cache_data.end = cache_max;
-        self.cache_data.update = true;
+        state.heads[index] = next;
+        state.hash ^= hash::key(hash::HEAD, index, old_head as u64)
+            ^ hash::key(hash::HEAD, index, next as u64);
+        if let (Some(profile), Some(start)) = (&mut self.profile, start) {
+            // heads index animations, the group is counted when profiling only
+            let group = groups(&context.animations[..head]);
+            let mut cells = context.animations[head..next]
+                .iter()
+                .flat_map(|animation| {
+                    let (start, end) = animation.target.bounds();
+                    (start..end).map(|i| i >> CELL_SHIFT)
+                })
+                .collect::<Vec<_>>();
+            cells.sort_unstable();
+            cells.dedup();
+            let counts = (animations, cells.len(), state.iters - iters);
+            profile.record(index, group, counts, start.elapsed());
+        }
+        state.cache_hit.index = cache_index;
+        state.cache_data.start = cache_min;
+        state.cache_data.end = cache_max;
+        state.cache_data.update = true;
+        Ok(Outcome::Fired {
+            trigger: Trigger::from(target.map(|referer| referer.index())),
+            animations,
+        })
     }
 
     pub fn update_filter(&mut self, x: f32, y: f32) {
-        self.cache_hit.x = x;
-        self.cache_hit.y = y;
-        for (i, state) in self.states_dyn.iter().enumerate() {
-            if state.is_visible() && state.contains(x, y) {
-                self.filter.set(i);
+        let state = &mut self.state;
+        state.cache_hit.x = x;
+        state.cache_hit.y = y;
+        state.iters += state.states_dyn.len();
+        for (i, state_dyn) in state.states_dyn.iter().enumerate() {
+            if state_dyn.is_visible() && state_dyn.contains(x, y) {
+                state.filter.set(i);
             } else {
-                self.filter.unset(i);
+                state.filter.unset(i);
             }
         }
     }
 
     pub fn render(&self, scale: f32, background: Color) -> Canvas<Color> {
-        let width = (self.width * scale) as usize;
-        let height = (self.height * scale) as usize;
+        let width = (self.program.width * scale) as usize;
+        let height = (self.program.height * scale) as usize;
         let mut canvas = Canvas::new(width, height, background);
-        for i in 0..self.states_dyn.len() {
+        for i in 0..self.state.states_dyn.len() {
             let ShapeDynState {
                 x,
                 y,
                 w,
                 h,
                 visibility: visibiliy,
-            } = self.states_dyn[i];
+            } = self.state.states_dyn[i];
             if visibiliy.is_visible() {
-                let ShapeConstState { color, .. } = self.states_const[i];
+                let ShapeConstState { color, .. } = self.program.states_const[i];
                 let x = (x * scale + 0.5) as isize;
                 let y = (y * scale + 0.5) as isize;
                 let w = (w * scale + 0.5) as isize;
@@ -440,8 +615,84 @@ impl Presentation {
     }
 }
 
+impl Runner for Presentation {
+    const KIND: RunnerKind = RunnerKind::Basic;
+    type Snapshot = (Vec<ShapeView>, Vec<usize>);
+
+    fn build(slide: Slide, config: Config) -> Result<Self> {
+        let partition =
+            (config.hit_test == HitTest::Partition).then(|| Partition::from_slide(&slide));
+        let (mut program, state) = compile(slide)?;
+        program.partition = partition;
+        program.hit_test = config.hit_test;
+        program.show_end = config.show_end;
+        let mut presentation = Presentation::new(Arc::new(program), state);
+        if config.profile {
+            let contexts = presentation.program.timeline.contexts.len();
+            presentation.profile = Some(Profile::new(contexts));
+        }
+        Ok(presentation)
+    }
+    fn click(&mut self, x: f32, y: f32) -> Result<Outcome> {
+        Presentation::click(self, x, y)
+    }
+    fn under(&mut self, x: f32, y: f32) -> Option<usize> {
+        self.hit(x, y)
+    }
+    fn render(&self, scale: f32, background: Color) -> Canvas<Color> {
+        Presentation::render(self, scale, background)
+    }
+    fn iters(&self) -> usize {
+        self.state.iters
+    }
+    fn set_iters(&mut self, iters: usize) {
+        self.state.iters = iters;
+    }
+    fn len(&self) -> usize {
+        self.state.states_dyn.len()
+    }
+    fn shape(&self, index: usize) -> ShapeView {
+        let state_dyn = &self.state.states_dyn[index];
+        ShapeView {
+            visible: state_dyn.is_visible(),
+            x: state_dyn.x,
+            y: state_dyn.y,
+            w: state_dyn.w,
+            h: state_dyn.h,
+            color: self.program.states_const[index].color,
+        }
+    }
+    fn name(&self, index: usize) -> &'static str {
+        self.program.names[index]
+    }
+    fn state_hash(&self) -> u64 {
+        self.state.hash
+    }
+    fn snapshot(&self) -> Self::Snapshot {
+        (self.shapes(), self.state.heads.clone())
+    }
+    fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+}
+
+// Grid with cells about the size of a typical shape
+pub fn index(states_dyn: &[ShapeDynState], width: f32, height: f32) -> Grid {
+    let mut sides = states_dyn
+        .iter()
+        .map(|state| state.w.max(state.h))
+        .collect::<Vec<_>>();
+    sides.sort_by(f32::total_cmp);
+    let side = sides.get(sides.len() / 2).copied().unwrap_or(width);
+    let mut grid = Grid::new(width, height, side.max(width.max(height) / 256.));
+    for (i, state) in states_dyn.iter().enumerate() {
+        grid.insert(i, state.rect());
+    }
+    grid
+}
+
 pub fn apply_effect(
-    effect: &mut Effect,
+    effect: &Effect,
     state_dyn: &mut ShapeDynState,
     state_const: &ShapeConstState,
 ) -> (bool, bool) {
@@ -459,17 +710,10 @@ pub fn apply_effect(
         }
         Effect::Appear => {}
         Effect::Disappear => {}
-        Effect::SlideIn { .. } => {
+        Effect::SlideIn { .. } | Effect::SlideOut { .. } => {
             state_dyn.x = state_const.x;
             state_dyn.y = state_const.y;
         }
-        Effect::SlideOut { complete, .. } => {
-            if !*complete {
-                unimplemented!("incomplete SlideOut");
-            }
-            state_dyn.x = -state_const.x;
-            state_dyn.y = -state_const.y;
-        }
     }
     match (old_visibility, state_dyn.visibility) {
         (Visibility::Unknown, Visibility::Hidden) | (Visibility::Visible, Visibility::Hidden) => {
@@ -485,30 +729,28 @@ pub fn init_effect(
     _states_dyn: &[ShapeDynState],
     states_const: &[ShapeConstState],
 ) {
-    match effect {
-        Effect::Path {
-            path,
-            x,
-            y,
-            relative,
-        } => {
-            *path = Vec::new();
-            let mut cx = f32::MAX;
-            let mut cy = f32::MAX;
-            for state in states_const {
-                if state.x < cx {
-                    cx = state.x;
-                }
-                if state.y < cy {
-                    cy = state.y;
-                }
+    if let Effect::Path {
+        path,
+        x,
+        y,
+        relative,
+    } = effect
+    {
+        *path = Vec::new();
+        let mut cx = f32::MAX;
+        let mut cy = f32::MAX;
+        for state in states_const {
+            if state.x < cx {
+                cx = state.x;
             }
-            if !*relative {
-                *x -= cx;
-                *y -= cy;
+            if state.y < cy {
+                cy = state.y;
             }
         }
-        _ => {}
+        if !*relative {
+            *x -= cx;
+            *y -= cy;
+        }
     }
 }
 
@@ -517,10 +759,22 @@ pub fn init_context(
     refs: &[Referer],
     shapes_dyn: &mut [ShapeDynState],
     shapes_const: &mut [ShapeConstState],
-) {
+) -> Result<()> {
     for animation in &mut context.animations {
         let old_index = animation.target.index();
-        let target = refs[old_index];
+        let target = *refs.get(old_index).ok_or(Error::DanglingReferer {
+            referer: old_index,
+            shapes: refs.len(),
+        })?;
+        if let Effect::SlideOut {
+            complete: false, ..
+        } = animation.effect
+        {
+            return Err(Error::UnsupportedEffect {
+                effect: animation.effect.name(),
+                shape: old_index,
+            });
+        }
         animation.target = target;
         let (start, end) = target.bounds();
         init_effect(
@@ -538,4 +792,5 @@ pub fn init_context(
             }
         }
     }
+    Ok(())
 }
@@ -0,0 +1,81 @@
+// Runs of one presentation from many input scripts, spread over threads. Every
+// run starts from its own clone of the presentation as built, a fresh machine
+// state sharing the compiled program of the others.
+
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use super::{Halt, Run, Runner};
+use crate::{render::Canvas, Color, Result};
+
+// What every run does once its script is played
+pub struct Plan<R> {
+    pub point: (f32, f32),
+    pub halts: Vec<Halt<R>>,
+    pub limit: usize,
+    pub scale: f32,
+    pub background: Color,
+}
+
+pub struct Report {
+    // run of the plan, its clicks counting those of the script
+    pub result: Result<Run>,
+    // final state of the run, rendered even when it failed
+    pub render: Canvas<Color>,
+}
+
+fn play<R: Runner>(presentation: &R, script: &[(f32, f32)], plan: &Plan<R>) -> Report {
+    let mut presentation = presentation.clone();
+    let result = script
+        .iter()
+        .try_for_each(|&(x, y)| presentation.click(x, y).map(drop))
+        .and_then(|_| presentation.run(plan.point, &plan.halts, plan.limit))
+        .map(|run| Run {
+            clicks: script.len() + run.clicks,
+            ..run
+        });
+    Report {
+        result,
+        render: presentation.render(plan.scale, plan.background),
+    }
+}
+
+// Plays every script then the plan on a clone of the presentation, with up to
+// threads workers, all of them available if 0. The reports are in the order
+// of the scripts.
+pub fn run<R: Runner + Sync>(
+    presentation: &R,
+    scripts: &[Vec<(f32, f32)>],
+    plan: &Plan<R>,
+    threads: usize,
+) -> Vec<Report> {
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+        threads => threads,
+    };
+    let next = AtomicUsize::new(0);
+    let mut reports = thread::scope(|scope| {
+        let workers = (0..threads.min(scripts.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut reports = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(script) = scripts.get(index) else {
+                            return reports;
+                        };
+                        reports.push((index, play(presentation, script, plan)));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    reports.sort_by_key(|(index, _)| *index);
+    reports.into_iter().map(|(_, report)| report).collect()
+}
@@ -0,0 +1,1170 @@
+// Memoising version of the minimal runner for runs of clicks at its hot point.
+// A run is split into segments of up to span clicks, each recorded with the
+// state bits it read and its net effect on the shapes it touched. When the
+// state looks the same again through the window of a recorded segment, its
+// effect is applied at once instead of replaying its clicks.
+//
+// The window is positioned on its anchor, the topmost local shape covering the
+// hot point, and addresses local shapes by their offset from it, so that the
+// same local state at other indices hits the same entry, as in HashLife. Each
+// offset is checked against the class of the shape found there, its name and
+// its context with targets as offsets, so that the clicks do the same there.
+// Global shapes, targeted from more than FAN_IN contexts or only from such
+// ones, hold the state shared by the whole show, like the registers of the
+// rule110 automaton: they keep their indices and are read whole. Segments
+// whose window holds global shapes are pinned to their anchor. Click groups of
+// global contexts on local shapes, the sweeps, are played at their indices
+// with the cell operations of the minimal runner, once per entry.
+//
+// The entries of a key form a decision tree over the state read, in the order
+// the segments read it, so that a lookup reads as much as one segment did. A
+// segment reads at once that no local shape it did not write covers the hot
+// point below its anchor, rather than shape by shape.
+//
+// Unlike HashLife, segments are not composed into longer ones. A hit skips at
+// most span clicks, so the gain over the minimal runner is a constant factor
+// however long the run, and the build costs more than the minimal one.
+// benches/runners.rs times both with and without the builds.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    hash::{BuildHasherDefault, Hasher},
+    ops::Range,
+    sync::Arc,
+};
+
+use super::{
+    click_through, minimal,
+    minimal::{BasicContext, BasicTimeline},
+    profile::Profile,
+    Config, Halt, Outcome, Run, Runner, RunnerKind, ShapeView, ShowEnd,
+};
+use crate::{
+    filters::{Filter, CELL_MASK, CELL_SHIFT},
+    render::Canvas,
+    Color, Result, Slide,
+};
+
+// Contexts targeting a shape past which it is global
+const FAN_IN: usize = 4;
+
+// Multiplicative hasher for the indices the traces are keyed on, much faster
+// than the default one on the miss path
+#[derive(Default)]
+struct Fx(u64);
+
+impl Hasher for Fx {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        bytes.iter().for_each(|byte| self.write_u64(*byte as u64));
+    }
+    fn write_u64(&mut self, word: u64) {
+        self.0 = (self.0.rotate_left(5) ^ word).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+    fn write_usize(&mut self, word: usize) {
+        self.write_u64(word as u64);
+    }
+}
+
+type HashMap<K, V> = std::collections::HashMap<K, V, BuildHasherDefault<Fx>>;
+type HashSet<T> = std::collections::HashSet<T, BuildHasherDefault<Fx>>;
+
+// Net effect of clicks on a shape
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct ShapeOp {
+    visible: Option<bool>,
+    // one per hot point plane
+    planes: Vec<Option<bool>>,
+    place: Option<u32>,
+}
+
+impl ShapeOp {
+    fn new(planes: usize) -> Self {
+        Self {
+            visible: None,
+            planes: vec![None; planes],
+            place: None,
+        }
+    }
+    // Applies op after this one
+    fn then(&mut self, op: &ShapeOp) {
+        self.visible = op.visible.or(self.visible);
+        for (plane, op) in self.planes.iter_mut().zip(&op.planes) {
+            *plane = op.or(*plane);
+        }
+        self.place = op.place.or(self.place);
+    }
+    // Leaves out what op writes
+    fn without(&mut self, op: &ShapeOp) {
+        self.visible = self.visible.filter(|_| op.visible.is_none());
+        for (plane, op) in self.planes.iter_mut().zip(&op.planes) {
+            *plane = plane.filter(|_| op.is_none());
+        }
+        self.place = self.place.filter(|_| op.place.is_none());
+    }
+    fn is_empty(&self) -> bool {
+        self.visible.is_none() && self.planes.iter().all(Option::is_none) && self.place.is_none()
+    }
+}
+
+// Shape of a segment, global ones keep their index
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Addr {
+    Rel(isize),
+    Abs(usize),
+}
+
+// State a segment reads, local shapes at their offset from the anchor
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Loc {
+    Class(isize),
+    Visible(isize),
+    Plane(isize),
+    // visible and covering the hot point
+    Combined(isize),
+    Head(Addr),
+    // visibility and plane cells of the global shapes
+    GlobalVisible(usize),
+    GlobalPlane(usize),
+    // global shapes below the offsets, none between them
+    Span(isize, isize),
+    // no local shape between the offsets covering the hot point, but those
+    // the sweeps of the mask hide
+    Clear(isize, isize, usize),
+}
+
+// State read while recording, at the indices of the shapes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Read {
+    Class(usize),
+    Visible(usize),
+    Plane(usize),
+    Combined(usize),
+    Head(usize),
+    GlobalVisible(usize),
+    GlobalPlane(usize),
+    Clear(usize, usize, usize),
+}
+
+// Writes of a segment in order, a sweep being a click group of a global
+// context on local shapes, played at their indices
+#[derive(Debug)]
+enum Step<Shapes> {
+    Shapes(Shapes),
+    Sweep(usize, usize),
+}
+
+#[derive(Debug)]
+struct Entry {
+    steps: Vec<Step<Vec<(Addr, ShapeOp)>>>,
+    heads: Vec<(Addr, usize)>,
+    clicks: usize,
+}
+
+// Entries by the values of the locations they read, those all of them read
+// the same first
+#[derive(Clone)]
+struct Node {
+    path: Vec<(Loc, usize)>,
+    next: Next,
+}
+
+#[derive(Clone)]
+enum Next {
+    Read(Loc, Vec<(usize, Node)>),
+    Leaf(Arc<Entry>),
+}
+
+impl Node {
+    fn chain(reads: &[(Loc, usize)], entry: Arc<Entry>) -> Self {
+        Node {
+            path: reads.to_vec(),
+            next: Next::Leaf(entry),
+        }
+    }
+
+    fn leaves(&self) -> usize {
+        match &self.next {
+            Next::Read(_, children) => children.iter().map(|(_, child)| child.leaves()).sum(),
+            Next::Leaf(_) => 1,
+        }
+    }
+
+    // Adds the entry, replacing the entries reading other locations on its
+    // path, and returns how many were replaced
+    fn graft(&mut self, reads: &[(Loc, usize)], entry: Arc<Entry>) -> usize {
+        let path = self.path.iter().zip(reads);
+        let shared = path.take_while(|(path, read)| path == read).count();
+        if let Some(&(loc, value)) = self.path.get(shared) {
+            return match reads.get(shared) {
+                Some(&(read, other)) if read == loc => {
+                    let rest = self.path.split_off(shared + 1);
+                    self.path.truncate(shared);
+                    let next = std::mem::replace(&mut self.next, Next::Read(loc, vec![]));
+                    let old = Node { path: rest, next };
+                    let new = Node::chain(&reads[shared + 1..], entry);
+                    self.next = Next::Read(loc, vec![(value, old), (other, new)]);
+                    0
+                }
+                _ => {
+                    let replaced = self.leaves();
+                    *self = Node::chain(reads, entry);
+                    replaced
+                }
+            };
+        }
+        match (&mut self.next, &reads[shared..]) {
+            (Next::Read(loc, children), [(read, value), rest @ ..]) if loc == read => {
+                match children.iter_mut().find(|(child, _)| child == value) {
+                    Some((_, child)) => child.graft(rest, entry),
+                    None => {
+                        children.push((*value, Node::chain(rest, entry)));
+                        0
+                    }
+                }
+            }
+            (_, rest) => {
+                let replaced = self.leaves();
+                self.path.extend_from_slice(rest);
+                self.next = Next::Leaf(entry);
+                replaced
+            }
+        }
+    }
+}
+
+// Class of the anchor, and the anchor itself for pinned segments
+type Key = (usize, Option<usize>);
+
+// Segment being recorded
+struct Trace {
+    anchor: Option<usize>,
+    pinned: bool,
+    // value of each location before the segment wrote it, in the order read
+    reads: Vec<(Read, usize)>,
+    read: HashSet<Read>,
+    // local shapes whose class the clicks depend on
+    seen: HashSet<usize>,
+    steps: Vec<Step<HashMap<usize, ShapeOp>>>,
+    // whether the steps but the sweeps wrote the visibility and the hot point
+    // plane of local shapes
+    written: HashMap<usize, (bool, bool)>,
+    sweeps: HashSet<(usize, usize)>,
+    // mask of the hiding sweeps fired
+    fired: usize,
+    heads: HashMap<usize, usize>,
+    clicks: usize,
+    // visibility and hot point plane before the segment
+    start: (Filter, Filter),
+    // lowest local shape consulted up to the anchor
+    low: usize,
+    // local shapes written since they were consulted
+    dirty: BTreeSet<usize>,
+}
+
+impl Trace {
+    // Records the value unless the location was read or written before
+    fn read(&mut self, read: Read, value: usize) {
+        if self.read.insert(read) {
+            self.reads.push((read, value));
+        }
+    }
+
+    // Marks the local shape as one the clicks depend on
+    fn see(&mut self, shape: usize, class: usize) {
+        if self.seen.insert(shape) {
+            self.read(Read::Class(shape), class);
+        }
+    }
+}
+
+// Cloning it copies the memo, the entries are shared
+#[derive(Clone)]
+pub struct Presentation {
+    pub inner: minimal::Presentation,
+    // longest segment recorded
+    pub span: usize,
+    // most entries kept, the memo is cleared when it is full
+    pub capacity: usize,
+    plane: usize,
+    // net effect of each click group on the shapes, per context then main
+    ops: Vec<Vec<Vec<(usize, ShapeOp)>>>,
+    classes: Vec<usize>,
+    // local shapes each click group raises, for the sweeps
+    sweeps: Vec<Vec<Option<Vec<usize>>>>,
+    // sweeps writing each local shape
+    swept: Vec<Vec<(usize, usize, ShapeOp)>>,
+    // local shapes the first sweeps hide from the hot point, by their bit in
+    // the masks of the traces
+    hidden: HashMap<(usize, usize), usize>,
+    hides: Vec<Filter>,
+    global: Filter,
+    local: Filter,
+    globals: Vec<usize>,
+    global_cells: Vec<usize>,
+    memo: HashMap<Key, Node>,
+    entries: usize,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl Presentation {
+    pub fn build(slide: Slide, config: Config) -> Result<Self> {
+        let inner = minimal::Presentation::build(slide, config)?;
+        let program = &inner.program;
+        // the minimal runner gives the target a plane
+        let plane = program
+            .targets
+            .iter()
+            .position(|target| *target == config.target)
+            .unwrap();
+        let planes = program.targets.len();
+        let timeline = &program.timeline;
+        let contexts = timeline.contexts.iter().chain([&timeline.main_context]);
+        let ops = contexts
+            .map(|context| decompose(context, planes))
+            .collect::<Vec<_>>();
+        let Classes {
+            global,
+            classes,
+            sweeps,
+            swept,
+        } = classify(&ops, &program.names, plane);
+        let len = classes.len();
+        let mut local = Filter::new(len);
+        (0..len)
+            .filter(|shape| !global.get(*shape))
+            .for_each(|shape| local.set(shape));
+        let mut hidden = HashMap::default();
+        let mut hides = vec![];
+        for (shape, sweeps) in swept.iter().enumerate() {
+            let hiding = sweeps
+                .iter()
+                .filter(|(_, _, op)| op.visible == Some(false) || op.planes[plane] == Some(false));
+            for &(context, group, _) in hiding {
+                let id = match hidden.get(&(context, group)) {
+                    Some(id) => *id,
+                    None if hides.len() < usize::BITS as usize => {
+                        hidden.insert((context, group), hides.len());
+                        hides.push(Filter::new(len));
+                        hides.len() - 1
+                    }
+                    None => continue,
+                };
+                hides[id].set(shape);
+            }
+        }
+        let globals = (0..len).filter(|shape| global.get(*shape)).collect();
+        let global_cells = (0..global.cells())
+            .filter(|cell| global.cells[*cell] != 0)
+            .collect();
+        Ok(Self {
+            inner,
+            span: 32,
+            capacity: 1 << 16,
+            plane,
+            ops,
+            classes,
+            sweeps,
+            swept,
+            hidden,
+            hides,
+            global,
+            local,
+            globals,
+            global_cells,
+            memo: HashMap::default(),
+            entries: 0,
+            hits: 0,
+            misses: 0,
+        })
+    }
+
+    fn topmost(&self) -> Option<usize> {
+        let state = &self.inner.state;
+        state.visible.last_and(&state.planes[self.plane])
+    }
+
+    // Topmost local shape under the hot point
+    fn anchor(&self) -> Option<usize> {
+        let state = &self.inner.state;
+        let cells = state.visible.cells.iter();
+        let cells = cells
+            .zip(&state.planes[self.plane].cells)
+            .zip(&self.local.cells);
+        cells
+            .enumerate()
+            .rev()
+            .find_map(|(cell, ((visible, plane), local))| {
+                let combined = visible & plane & local;
+                let last = || usize::BITS - 1 - combined.leading_zeros();
+                (combined != 0).then(|| (cell << CELL_SHIFT) + last() as usize)
+            })
+    }
+
+    fn trace(&self, anchor: Option<usize>) -> Trace {
+        let state = &self.inner.state;
+        let plane = &state.planes[self.plane];
+        let mut trace = Trace {
+            anchor,
+            pinned: anchor.is_none(),
+            reads: vec![],
+            read: HashSet::default(),
+            seen: HashSet::default(),
+            steps: vec![],
+            written: HashMap::default(),
+            sweeps: HashSet::default(),
+            fired: 0,
+            heads: HashMap::default(),
+            clicks: 0,
+            start: (state.visible.clone(), plane.clone()),
+            low: anchor.map_or(0, |anchor| anchor + 1),
+            dirty: BTreeSet::new(),
+        };
+        for &cell in &self.global_cells {
+            let global = self.global.cells[cell];
+            trace.read(
+                Read::GlobalVisible(cell),
+                state.visible.cells[cell] & global,
+            );
+            trace.read(Read::GlobalPlane(cell), plane.cells[cell] & global);
+        }
+        trace
+    }
+
+    // Records the reads telling the topmost shape under the hot point: the
+    // local ones between it and the anchor, those above the anchor being hidden
+    // from the hot point, and those the segment may have shown above
+    fn scan(&self, topmost: Option<usize>, trace: &mut Trace) {
+        let global = |shape| self.global.get(shape);
+        match (topmost, trace.anchor) {
+            (None, _) => trace.pinned = true,
+            (Some(shape), Some(anchor)) if global(shape) && shape < anchor => trace.pinned = true,
+            _ => {}
+        }
+        let low = topmost.unwrap_or(0);
+        if trace.anchor.is_some() && low < trace.low {
+            if !global(low) {
+                self.consult(low, trace);
+            }
+            // the ones below are read at once unless the segment hid some
+            // other than by its sweeps
+            let below = low + 1..trace.low;
+            let (visible, plane) = &trace.start;
+            if !below.is_empty() && self.clear(visible, plane, below.clone(), trace.fired) {
+                let read = Read::Clear(below.start, below.end - 1, trace.fired);
+                trace.read(read, 1);
+            } else {
+                for shape in below.filter(|shape| !global(*shape)) {
+                    self.consult(shape, trace);
+                }
+            }
+            trace.low = low;
+        }
+        // the others read the same as when they were last consulted
+        while let Some(shape) = trace.dirty.range(low..).next().copied() {
+            trace.dirty.remove(&shape);
+            self.consult(shape, trace);
+        }
+    }
+
+    // Whether no local shape in the range covers the hot point, but those the
+    // sweeps of the mask hide
+    fn clear(&self, visible: &Filter, plane: &Filter, shapes: Range<usize>, fired: usize) -> bool {
+        let (first, last) = (shapes.start >> CELL_SHIFT, (shapes.end - 1) >> CELL_SHIFT);
+        (first..=last).all(|cell| {
+            let mut combined = visible.cells[cell] & plane.cells[cell] & self.local.cells[cell];
+            for id in bits(fired) {
+                combined &= !self.hides[id].cells[cell];
+            }
+            if cell == first {
+                combined &= usize::MAX << (shapes.start & CELL_MASK);
+            }
+            if cell == last {
+                combined &= usize::MAX >> (CELL_MASK - ((shapes.end - 1) & CELL_MASK));
+            }
+            combined == 0
+        })
+    }
+
+    // Records the reads telling whether the local shape covers the hot point,
+    // those of the parts the segment did not write
+    fn consult(&self, shape: usize, trace: &mut Trace) {
+        let state = &self.inner.state;
+        let visible = state.visible.get(shape);
+        let covers = state.planes[self.plane].get(shape);
+        trace.see(shape, self.classes[shape]);
+        let (mut visibility, mut plane) = trace.written.get(&shape).copied().unwrap_or_default();
+        for (context, group, op) in &self.swept[shape] {
+            if trace.sweeps.contains(&(*context, *group)) {
+                visibility |= op.visible.is_some();
+                plane |= op.planes[self.plane].is_some();
+            }
+        }
+        match (visibility, plane) {
+            (false, false) => trace.read(Read::Combined(shape), usize::from(visible && covers)),
+            (true, false) if visible => trace.read(Read::Plane(shape), usize::from(covers)),
+            (false, true) if covers => trace.read(Read::Visible(shape), usize::from(visible)),
+            _ => {}
+        }
+    }
+
+    // Context fired by a click on the shape, the main one is past the others
+    fn fired(&self, topmost: Option<usize>) -> usize {
+        match topmost {
+            Some(index) if !self.ops[index].is_empty() => index,
+            _ => self.classes.len(),
+        }
+    }
+
+    // Plays a click on the hot point, recording it in the trace, false if the
+    // show is over
+    fn step(&mut self, index: usize, trace: &mut Trace) -> bool {
+        let len = self.classes.len();
+        let (groups, state) = (&self.ops[index], &mut self.inner.state);
+        let old = state.heads[index];
+        if !trace.heads.contains_key(&index) {
+            trace.read(Read::Head(index), old);
+        }
+        let head = if old == groups.len() {
+            if index == len && self.inner.program.show_end == ShowEnd::Stop {
+                return false;
+            }
+            usize::from(!groups.is_empty())
+        } else {
+            old + 1
+        };
+        state.heads[index] = head;
+        trace.heads.insert(index, head);
+        if index != len && !self.global.get(index) {
+            trace.see(index, self.classes[index]);
+        }
+        let Some(group) = head.checked_sub(1) else {
+            trace.clicks += 1;
+            return true;
+        };
+        if let Some(raised) = &self.sweeps[index][group] {
+            sweep(state, &self.inner.program.timeline, index, group);
+            trace.sweeps.insert((index, group));
+            if let Some(id) = self.hidden.get(&(index, group)) {
+                trace.fired |= 1 << id;
+            }
+            trace.dirty.extend(raised);
+            trace.steps.push(Step::Sweep(index, group));
+            trace.clicks += 1;
+            return true;
+        }
+        if !matches!(trace.steps.last(), Some(Step::Shapes(_))) {
+            trace.steps.push(Step::Shapes(HashMap::default()));
+        }
+        let Some(Step::Shapes(shapes)) = trace.steps.last_mut() else {
+            unreachable!()
+        };
+        for (shape, op) in &groups[group] {
+            apply(state, *shape, op);
+            let planes = op.planes.len();
+            let slot = shapes.entry(*shape).or_insert_with(|| ShapeOp::new(planes));
+            slot.then(op);
+            if !self.global.get(*shape) {
+                let written = trace.written.entry(*shape).or_default();
+                written.0 |= op.visible.is_some();
+                written.1 |= op.planes[self.plane].is_some();
+                trace.dirty.insert(*shape);
+            }
+        }
+        trace.clicks += 1;
+        true
+    }
+
+    fn key(&self, anchor: Option<usize>, pinned: bool) -> Key {
+        let class = anchor.map_or(usize::MAX, |anchor| self.classes[anchor]);
+        (class, pinned.then(|| anchor.unwrap_or(usize::MAX)))
+    }
+
+    // Key, locations read with their values, and entry of the recorded segment
+    fn entry(&self, trace: Trace) -> (Key, Vec<(Loc, usize)>, Entry) {
+        let base = trace.anchor.unwrap_or(0);
+        let global = |shape| self.global.get(shape);
+        let offset = |shape: usize| shape as isize - base as isize;
+        let addr = |shape| match global(shape) {
+            true => Addr::Abs(shape),
+            false => Addr::Rel(offset(shape)),
+        };
+        let context = |context| match context == self.classes.len() {
+            true => Addr::Abs(context),
+            false => addr(context),
+        };
+        let locals = trace
+            .seen
+            .iter()
+            .chain(trace.written.keys())
+            .chain(trace.heads.keys())
+            .filter(|shape| **shape < self.classes.len() && !global(**shape))
+            .map(|shape| offset(*shape));
+        // the shapes read down from the anchor
+        let read = trace.anchor.map(|anchor| offset(trace.low.min(anchor)));
+        let low = locals.clone().chain(read).min();
+        let high = locals.chain(trace.anchor.map(|_| 0)).max();
+        let mut pinned = trace.pinned;
+        let mut reads = trace
+            .reads
+            .iter()
+            .map(|&(read, value)| {
+                let loc = match read {
+                    Read::Class(shape) => Loc::Class(offset(shape)),
+                    Read::Visible(shape) => Loc::Visible(offset(shape)),
+                    Read::Plane(shape) => Loc::Plane(offset(shape)),
+                    Read::Combined(shape) => Loc::Combined(offset(shape)),
+                    Read::Head(index) => Loc::Head(context(index)),
+                    Read::GlobalVisible(cell) => Loc::GlobalVisible(cell),
+                    Read::GlobalPlane(cell) => Loc::GlobalPlane(cell),
+                    Read::Clear(low, high, fired) => Loc::Clear(offset(low), offset(high), fired),
+                };
+                (loc, value)
+            })
+            .collect::<Vec<_>>();
+        if let (Some(low), Some(high)) = (low, high) {
+            let span = Loc::Span(low, high);
+            let below = self.span(span, base);
+            pinned |= below == usize::MAX;
+            if !pinned {
+                reads.push((span, below));
+            }
+        }
+        let steps = self
+            .compose(trace.steps)
+            .into_iter()
+            .map(|step| match step {
+                Step::Shapes(shapes) => Step::Shapes(
+                    shapes
+                        .into_iter()
+                        .map(|(shape, op)| (addr(shape), op))
+                        .collect(),
+                ),
+                Step::Sweep(context, group) => Step::Sweep(context, group),
+            });
+        let entry = Entry {
+            steps: steps.collect(),
+            heads: trace
+                .heads
+                .into_iter()
+                .map(|(index, head)| (context(index), head))
+                .collect(),
+            clicks: trace.clicks,
+        };
+        (self.key(trace.anchor, pinned), reads, entry)
+    }
+
+    // Steps with the same net effect, leaving out the writes a later step
+    // makes again, so that a sweep is played once
+    fn compose(
+        &self,
+        steps: Vec<Step<HashMap<usize, ShapeOp>>>,
+    ) -> Vec<Step<Vec<(usize, ShapeOp)>>> {
+        let mut swept = HashSet::default();
+        let mut composed: Vec<Step<HashMap<usize, ShapeOp>>> = vec![];
+        for step in steps.into_iter().rev() {
+            match step {
+                Step::Sweep(context, group) => {
+                    if swept.insert((context, group)) {
+                        composed.push(Step::Sweep(context, group));
+                    }
+                }
+                Step::Shapes(mut shapes) => {
+                    for (shape, op) in &mut shapes {
+                        let sweeps = self.swept.get(*shape).into_iter().flatten();
+                        for (context, group, sweep) in sweeps {
+                            if swept.contains(&(*context, *group)) {
+                                op.without(sweep);
+                            }
+                        }
+                    }
+                    shapes.retain(|_, op| !op.is_empty());
+                    match composed.last_mut() {
+                        Some(Step::Shapes(later)) => {
+                            for (shape, mut op) in shapes {
+                                if let Some(later) = later.get(&shape) {
+                                    op.then(later);
+                                }
+                                later.insert(shape, op);
+                            }
+                        }
+                        _ if shapes.is_empty() => {}
+                        _ => composed.push(Step::Shapes(shapes)),
+                    }
+                }
+            }
+        }
+        composed.reverse();
+        let steps = composed.into_iter().map(|step| match step {
+            Step::Shapes(shapes) => Step::Shapes(shapes.into_iter().collect()),
+            Step::Sweep(context, group) => Step::Sweep(context, group),
+        });
+        steps.collect()
+    }
+
+    fn shape(&self, base: usize, offset: isize) -> Option<usize> {
+        base.checked_add_signed(offset)
+            .filter(|shape| *shape < self.classes.len())
+    }
+
+    // Global shapes below the span, none if some are in it
+    fn span(&self, span: Loc, base: usize) -> usize {
+        let Loc::Span(low, high) = span else {
+            unreachable!()
+        };
+        match (self.shape(base, low), self.shape(base, high)) {
+            (Some(low), Some(high)) => {
+                let below = self.globals.partition_point(|global| *global < low);
+                match self.globals.get(below) {
+                    Some(global) if *global <= high => usize::MAX,
+                    _ => below,
+                }
+            }
+            _ => usize::MAX,
+        }
+    }
+
+    fn value(&self, loc: Loc, base: usize) -> usize {
+        let state = &self.inner.state;
+        let plane = &state.planes[self.plane];
+        let shape = |offset| self.shape(base, offset);
+        let bit = |offset, get: &dyn Fn(usize) -> bool| {
+            shape(offset).map_or(usize::MAX, |shape| usize::from(get(shape)))
+        };
+        match loc {
+            Loc::Class(offset) => shape(offset).map_or(usize::MAX, |shape| self.classes[shape]),
+            Loc::Visible(offset) => bit(offset, &|shape| state.visible.get(shape)),
+            Loc::Plane(offset) => bit(offset, &|shape| plane.get(shape)),
+            Loc::Combined(offset) => bit(offset, &|shape| {
+                state.visible.get(shape) && plane.get(shape)
+            }),
+            Loc::Head(Addr::Rel(offset)) => {
+                shape(offset).map_or(usize::MAX, |shape| state.heads[shape])
+            }
+            Loc::Head(Addr::Abs(context)) => state.heads[context],
+            Loc::GlobalVisible(cell) => state.visible.cells[cell] & self.global.cells[cell],
+            Loc::GlobalPlane(cell) => plane.cells[cell] & self.global.cells[cell],
+            Loc::Span(..) => self.span(loc, base),
+            Loc::Clear(low, high, fired) => match (shape(low), shape(high)) {
+                (Some(low), Some(high)) => {
+                    usize::from(self.clear(&state.visible, plane, low..high + 1, fired))
+                }
+                _ => usize::MAX,
+            },
+        }
+    }
+
+    // Recorded segment starting from the state, fitting in the clicks left
+    fn lookup(&self, anchor: Option<usize>, left: usize) -> Option<Arc<Entry>> {
+        let base = anchor.unwrap_or(0);
+        let keys = [self.key(anchor, false), self.key(anchor, true)];
+        keys.into_iter().find_map(|key| {
+            let mut node = self.memo.get(&key)?;
+            loop {
+                let mut path = node.path.iter();
+                if path.any(|(loc, value)| self.value(*loc, base) != *value) {
+                    return None;
+                }
+                match &node.next {
+                    Next::Read(loc, children) => {
+                        let value = self.value(*loc, base);
+                        node = &children.iter().find(|(child, _)| *child == value)?.1;
+                    }
+                    Next::Leaf(entry) => return (entry.clicks <= left).then(|| entry.clone()),
+                }
+            }
+        })
+    }
+
+    fn insert(&mut self, trace: Trace) {
+        let (key, reads, entry) = self.entry(trace);
+        if self.entries == self.capacity {
+            self.memo.clear();
+            self.entries = 0;
+        }
+        let entry = Arc::new(entry);
+        match self.memo.get_mut(&key) {
+            Some(node) => self.entries -= node.graft(&reads, entry),
+            None => {
+                self.memo.insert(key, Node::chain(&reads, entry));
+            }
+        }
+        self.entries += 1;
+    }
+
+    fn replay(&mut self, entry: &Entry, anchor: Option<usize>) {
+        let base = anchor.unwrap_or(0);
+        let resolve = |addr| match addr {
+            Addr::Rel(offset) => base.wrapping_add_signed(offset),
+            Addr::Abs(index) => index,
+        };
+        let (program, state) = (&*self.inner.program, &mut self.inner.state);
+        for step in &entry.steps {
+            match step {
+                Step::Shapes(shapes) => {
+                    for (addr, op) in shapes {
+                        apply(state, resolve(*addr), op);
+                    }
+                }
+                Step::Sweep(context, group) => sweep(state, &program.timeline, *context, *group),
+            }
+        }
+        for &(addr, head) in &entry.heads {
+            state.heads[resolve(addr)] = head;
+        }
+    }
+
+    // First of the halts naming the topmost shape under the hot point
+    fn stops(&self, topmost: Option<usize>, halts: &[Halt<Self>]) -> Option<usize> {
+        let name = self.inner.program.names[topmost?];
+        halts
+            .iter()
+            .position(|halt| matches!(halt, Halt::Topmost(stop) if *stop == name))
+    }
+
+    // Clicks the hot point until a topmost halt holds, the show is over or
+    // limit clicks were played, skipping through the recorded segments
+    fn skip(&mut self, halts: &[Halt<Self>], limit: usize) -> Run {
+        let mut clicks = 0;
+        let halt = loop {
+            let mut topmost = self.topmost();
+            if let Some(halt) = self.stops(topmost, halts) {
+                break Some(halt);
+            }
+            if clicks == limit {
+                break None;
+            }
+            let anchor = self.anchor();
+            if let Some(entry) = self.lookup(anchor, limit - clicks) {
+                self.replay(&entry, anchor);
+                clicks += entry.clicks;
+                self.hits += 1;
+                continue;
+            }
+            self.misses += 1;
+            let mut trace = self.trace(anchor);
+            let (over, cut) = loop {
+                self.scan(topmost, &mut trace);
+                if !self.step(self.fired(topmost), &mut trace) {
+                    break (true, false);
+                }
+                if trace.clicks == self.span {
+                    break (false, false);
+                }
+                if clicks + trace.clicks == limit {
+                    break (false, true);
+                }
+                topmost = self.topmost();
+                if self.stops(topmost, halts).is_some() {
+                    self.scan(topmost, &mut trace);
+                    break (false, false);
+                }
+            };
+            clicks += trace.clicks;
+            // a segment cut by the limit would go on with the same reads
+            if trace.clicks > 0 && !cut {
+                self.insert(trace);
+            }
+            if over {
+                // the clicks left do nothing once the show is over
+                let end = halts
+                    .iter()
+                    .position(|halt| matches!(halt, Halt::EndOfShow));
+                if end.is_none() {
+                    clicks = limit;
+                }
+                break end;
+            }
+        };
+        self.inner.state.hash = self.inner.rehash();
+        Run {
+            clicks,
+            halt,
+            cycle: None,
+        }
+    }
+}
+
+// Clicks are played by the minimal runner, as are runs away from the hot point
+// and those halting on cycles or predicates, which look at the state after
+// every click. The iteration counter only counts the clicks played
+impl Runner for Presentation {
+    const KIND: RunnerKind = RunnerKind::Minimal;
+    type Snapshot = <minimal::Presentation as Runner>::Snapshot;
+
+    fn build(slide: Slide, config: Config) -> Result<Self> {
+        Presentation::build(slide, config)
+    }
+    fn click(&mut self, x: f32, y: f32) -> Result<Outcome> {
+        self.inner.click(x, y)
+    }
+    fn under(&mut self, x: f32, y: f32) -> Option<usize> {
+        self.inner.under(x, y)
+    }
+    fn render(&self, scale: f32, background: Color) -> Canvas<Color> {
+        self.inner.render(scale, background)
+    }
+    fn iters(&self) -> usize {
+        self.inner.iters()
+    }
+    fn set_iters(&mut self, iters: usize) {
+        self.inner.set_iters(iters);
+    }
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+    fn shape(&self, index: usize) -> ShapeView {
+        self.inner.shape(index)
+    }
+    fn name(&self, index: usize) -> &'static str {
+        self.inner.name(index)
+    }
+    fn state_hash(&self) -> u64 {
+        self.inner.state_hash()
+    }
+    fn snapshot(&self) -> Self::Snapshot {
+        self.inner.snapshot()
+    }
+    fn profile(&self) -> Option<&Profile> {
+        self.inner.profile()
+    }
+    fn run(&mut self, point: (f32, f32), halts: &[Halt<Self>], limit: usize) -> Result<Run> {
+        let hot = self.inner.program.targets[self.plane];
+        let skips = halts
+            .iter()
+            .all(|halt| matches!(halt, Halt::Topmost(_) | Halt::EndOfShow));
+        if point != hot || !skips {
+            return click_through(self, point, halts, limit);
+        }
+        Ok(self.skip(halts, limit))
+    }
+}
+
+fn apply(state: &mut minimal::State, shape: usize, op: &ShapeOp) {
+    match op.visible {
+        Some(true) => state.visible.set(shape),
+        Some(false) => state.visible.unset(shape),
+        None => {}
+    }
+    for (plane, op) in state.planes.iter_mut().zip(&op.planes) {
+        match op {
+            Some(true) => plane.set(shape),
+            Some(false) => plane.unset(shape),
+            None => {}
+        }
+    }
+    if let Some(place) = op.place {
+        state.places[shape] = place;
+    }
+}
+
+// Plays the click group of the context with its cell operations
+fn sweep(state: &mut minimal::State, timeline: &BasicTimeline, context: usize, group: usize) {
+    let context = timeline
+        .contexts
+        .get(context)
+        .unwrap_or(&timeline.main_context);
+    for animation in &context.animations[group] {
+        let cell = animation.cell;
+        let visibility = &animation.visibility;
+        state.visible.cells[cell] =
+            (state.visible.cells[cell] | visibility.set) & !visibility.unset;
+        for (plane, targeting) in state.planes.iter_mut().zip(&animation.targeting) {
+            plane.cells[cell] = (plane.cells[cell] | targeting.set) & !targeting.unset;
+        }
+        for &(shape, place) in &animation.moves {
+            state.places[shape] = place;
+        }
+    }
+}
+
+// Indices of the bits set in the word
+fn bits(mut word: usize) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        let bit = (word != 0).then(|| word.trailing_zeros() as usize)?;
+        word &= word - 1;
+        Some(bit)
+    })
+}
+
+// Effect of each click group of the context on each shape
+fn decompose(context: &BasicContext, planes: usize) -> Vec<Vec<(usize, ShapeOp)>> {
+    let groups = context.animations.iter().map(|group| {
+        let mut shapes = BTreeMap::new();
+        for animation in group {
+            let base = animation.cell << CELL_SHIFT;
+            let visibility = &animation.visibility;
+            for bit in bits(visibility.set) {
+                shape_op(&mut shapes, base + bit, planes).visible = Some(true);
+            }
+            for bit in bits(visibility.unset) {
+                shape_op(&mut shapes, base + bit, planes).visible = Some(false);
+            }
+            for (plane, targeting) in animation.targeting.iter().enumerate() {
+                for bit in bits(targeting.set) {
+                    shape_op(&mut shapes, base + bit, planes).planes[plane] = Some(true);
+                }
+                for bit in bits(targeting.unset) {
+                    shape_op(&mut shapes, base + bit, planes).planes[plane] = Some(false);
+                }
+            }
+            for &(shape, place) in &animation.moves {
+                shape_op(&mut shapes, shape, planes).place = Some(place);
+            }
+        }
+        shapes.into_iter().collect()
+    });
+    groups.collect()
+}
+
+fn shape_op(shapes: &mut BTreeMap<usize, ShapeOp>, shape: usize, planes: usize) -> &mut ShapeOp {
+    shapes.entry(shape).or_insert_with(|| ShapeOp::new(planes))
+}
+
+struct Classes {
+    global: Filter,
+    classes: Vec<usize>,
+    sweeps: Vec<Vec<Option<Vec<usize>>>>,
+    swept: Vec<Vec<(usize, usize, ShapeOp)>>,
+}
+
+// Global shapes, the sweeps, and classes of the shapes by name, context, with
+// the targets as offsets from the shape unless they are global, and sweeps
+fn classify(ops: &[Vec<Vec<(usize, ShapeOp)>>], names: &[&'static str], plane: usize) -> Classes {
+    let len = names.len();
+    let mut targeters = vec![HashSet::default(); len];
+    for (context, groups) in ops.iter().enumerate() {
+        for (shape, _) in groups.iter().flatten() {
+            if *shape != context {
+                targeters[*shape].insert(context);
+            }
+        }
+    }
+    let shared = |context: usize| context == len || targeters[context].len() > FAN_IN;
+    let mut global = Filter::new(len);
+    for (shape, contexts) in targeters.iter().enumerate() {
+        if shared(shape) || contexts.iter().all(|context| shared(*context)) {
+            global.set(shape);
+        }
+    }
+    let mut swept = vec![vec![]; len];
+    let sweeps = ops.iter().enumerate().map(|(context, groups)| {
+        let groups = groups.iter().enumerate().map(|(group, shapes)| {
+            let locals = shapes.iter().filter(|(shape, _)| !global.get(*shape));
+            if context != len && !global.get(context) || locals.clone().next().is_none() {
+                return None;
+            }
+            let raises = |op: &ShapeOp| op.visible == Some(true) || op.planes[plane] == Some(true);
+            let raised = locals
+                .clone()
+                .filter(|(_, op)| raises(op))
+                .map(|(shape, _)| *shape);
+            for (shape, op) in locals {
+                swept[*shape].push((context, group, op.clone()));
+            }
+            Some(raised.collect())
+        });
+        groups.collect()
+    });
+    let sweeps = sweeps.collect();
+    let mut ids = HashMap::default();
+    let classes = (0..len).map(|shape| {
+        let groups = ops[shape].iter().map(|group| {
+            let targets = group.iter().map(|(target, op)| {
+                let addr = match global.get(*target) {
+                    true => Addr::Abs(*target),
+                    false => Addr::Rel(*target as isize - shape as isize),
+                };
+                (addr, op.clone())
+            });
+            targets.collect::<Vec<_>>()
+        });
+        let signature = (
+            global.get(shape),
+            names[shape],
+            groups.collect::<Vec<_>>(),
+            swept[shape].clone(),
+        );
+        let next = ids.len();
+        *ids.entry(signature).or_insert(next)
+    });
+    let classes = classes.collect();
+    Classes {
+        global,
+        classes,
+        sweeps,
+        swept,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        experiments::{self, RULE110_CLOCK},
+        runners::{minimal, Config, Halt, ShowEnd},
+        z, MacroEffect,
+    };
+
+    type Minimal = minimal::Presentation;
+
+    // The memo ends where the minimal runner does, skipping through the
+    // periods of a wrapping show
+    #[test]
+    fn periods() {
+        let mut slide = Slide::new(40., 30.);
+        let shape = slide.rect(2., 2., 4., 4.).z(z!(1)).handle();
+        let cover = slide.rect(30., 20., 4., 4.).z(z!(2)).handle();
+        slide
+            .main()
+            .animate(shape, MacroEffect::Disappear)
+            .after_click()
+            .animate(shape, MacroEffect::Appear)
+            .animate(cover, MacroEffect::Path(0., 5.))
+            .after_click();
+        slide
+            .on(cover)
+            .animate(cover, MacroEffect::Place)
+            .after_click();
+        let config = Config {
+            target: (30., 20.),
+            show_end: ShowEnd::Wrap,
+            ..Config::default()
+        };
+        let mut minimal = Minimal::build(slide.clone(), config).unwrap();
+        for _ in 0..1000 {
+            minimal.click(30., 20.).unwrap();
+        }
+        let mut memo = Presentation::build(slide, config).unwrap();
+        memo.span = 4;
+        let halts = [Halt::Topmost("STOP"), Halt::EndOfShow];
+        let run = memo.run((30., 20.), &halts, 1000).unwrap();
+        assert_eq!((run.clicks, run.halt), (1000, None));
+        assert!(memo.hits > memo.misses);
+        assert_eq!(memo.inner.shapes(), minimal.shapes());
+        assert_eq!(memo.inner.state_hash(), minimal.state_hash());
+    }
+
+    // The memo reaches the STOP shape of rule110 after the clicks of the
+    // minimal runner, replaying the rows it recorded at other cells
+    #[test]
+    fn rule110() {
+        let config = Config {
+            target: RULE110_CLOCK,
+            ..Config::default()
+        };
+        let mut minimal = Minimal::build(experiments::rule110(), config).unwrap();
+        let halts = [Halt::Topmost("STOP"), Halt::EndOfShow];
+        let run = minimal.run(RULE110_CLOCK, &halts, 100_000).unwrap();
+        let mut memo = Presentation::build(experiments::rule110(), config).unwrap();
+        let halts = [Halt::Topmost("STOP"), Halt::EndOfShow];
+        let skipped = memo.run(RULE110_CLOCK, &halts, 100_000).unwrap();
+        assert_eq!((skipped.clicks, skipped.halt), (run.clicks, Some(0)));
+        assert!(memo.hits > 4 * memo.misses);
+        assert_eq!(memo.inner.shapes(), minimal.shapes());
+        assert_eq!(memo.inner.state_hash(), minimal.state_hash());
+    }
+}
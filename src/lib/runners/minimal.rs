@@ -1,9 +1,22 @@
-use std::{collections::HashMap, fmt, process::exit};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    mem::size_of,
+    sync::Arc,
+    time::Instant,
+};
 
+use super::{
+    profile::Profile, CompileReport, Config, HitTest, Outcome, Runner, RunnerKind, ShapeView,
+    ShowEnd, Trigger,
+};
 use crate::{
-    filters::{Cell, DoubleFilter, CELL_MASK, CELL_SHIFT},
+    error::{Error, Result},
+    filters::{Cell, Filter, Word},
+    hash, in_bounds,
+    partition::Partition,
     render::Canvas,
-    Color, Context, Effect, Shape, ShapeState, Slide,
+    Color, Context, Effect, Preset, Shape, ShapeState, Slide,
 };
 
 #[derive(Clone)]
@@ -17,208 +30,429 @@ pub struct ShapeConstState {
 }
 
 #[derive(Clone, Default)]
-pub struct CellOp {
-    pub set: Cell,
-    pub unset: Cell,
+pub struct CellOp<W: Word = Cell> {
+    pub set: W,
+    pub unset: W,
 }
 
-impl std::fmt::Debug for CellOp {
+impl<W: Word> std::fmt::Debug for CellOp<W> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = W::SIZE;
         f.debug_struct("CellOp")
-            .field("set", &format!("{:064b}", self.set))
-            .field("unset", &format!("{:064b}", self.unset))
+            .field("set", &format!("{:0width$b}", self.set))
+            .field("unset", &format!("{:0width$b}", self.unset))
             .finish()
     }
 }
 
-impl CellOp {
-    pub fn set(&mut self, val: Cell) {
+impl<W: Word> CellOp<W> {
+    pub fn set(&mut self, val: W) {
         self.set |= val;
         self.unset &= !val;
     }
-    pub fn unset(&mut self, val: Cell) {
+    pub fn unset(&mut self, val: W) {
         self.set &= !val;
         self.unset |= val;
     }
 }
 
 #[derive(Clone, Debug)]
-pub struct BasicAnimation {
-    cell: usize,
-    visibility: CellOp,
-    targeting: CellOp,
+pub struct BasicAnimation<W: Word = Cell> {
+    pub cell: usize,
+    pub visibility: CellOp<W>,
+    // one operation per hot point plane
+    pub targeting: Vec<CellOp<W>>,
+    // shapes of the cell moved to a position of their lattice
+    pub moves: Vec<(usize, u32)>,
+}
+
+// Set and unset masks of a click group over the whole filters
+#[derive(Clone, Debug)]
+pub struct Masks<W: Word = Cell> {
+    pub visibility: (Filter<W>, Filter<W>),
+    // one pair per hot point plane
+    pub targeting: Vec<(Filter<W>, Filter<W>)>,
 }
 
 #[derive(Clone, Default, Debug)]
-pub struct BasicContext {
-    pub head: usize,
-    pub animations: Vec<Vec<BasicAnimation>>,
+pub struct BasicContext<W: Word = Cell> {
+    pub animations: Vec<Vec<BasicAnimation<W>>>,
+    // animations of the slide each click group was merged from
+    pub sources: Vec<usize>,
+    // masks of the groups touching at least one cell in DENSE, applied
+    // filter wide rather than cell by cell
+    pub masks: Vec<Option<Box<Masks<W>>>>,
 }
 
 #[derive(Clone, Default, Debug)]
-pub struct BasicTimeline {
-    pub main_context: BasicContext,
-    pub contexts: Vec<BasicContext>,
+pub struct BasicTimeline<W: Word = Cell> {
+    pub main_context: BasicContext<W>,
+    pub contexts: Vec<BasicContext<W>>,
 }
 
-pub struct Presentation {
-    pub states_dyn: DoubleFilter,
+// Cells out of which a click group must touch one to get masks
+pub const DENSE: usize = 4;
+
+// Everything fixed once the slide is compiled, shared by the machines playing it
+pub struct Program<W: Word = Cell> {
+    // positions each shape can be at, starting with its home
+    pub lattice: Vec<Vec<(f32, f32)>>,
+    // candidates of the clicks away from the hot points, scanned otherwise
+    pub partition: Option<Partition>,
     pub states_const: Vec<ShapeConstState>,
-    pub timeline: BasicTimeline,
-    pub target: (f32, f32),
+    pub names: Vec<&'static str>,
+    pub timeline: BasicTimeline<W>,
+    pub targets: Vec<(f32, f32)>,
     pub width: f32,
     pub height: f32,
+    pub show_end: ShowEnd,
+    pub report: CompileReport,
+}
+
+#[derive(Clone)]
+pub struct State<W: Word = Cell> {
+    pub visible: Filter<W>,
+    // shapes covering each hot point wherever they are
+    pub planes: Vec<Filter<W>>,
+    // index in the lattice of the current position of each shape
+    pub places: Vec<u32>,
+    // head of each shape context, then of the main one
+    pub heads: Vec<usize>,
     pub iters: usize,
+    // hash of the visibility cells, places and context heads, kept up to
+    // date by click
+    pub hash: u64,
 }
 
-impl Presentation {
-    pub fn from(mut slide: Slide, target: (f32, f32)) -> Self {
-        slide.shapes.sort_by(|a, b| a.1.z().cmp(&b.1.z()));
-        let total_size = slide.shapes.iter().map(|e| e.1.size()).sum();
-        let mut refs = vec![0; slide.shapes.len()];
-        let mut shapes_dyn = DoubleFilter::new(total_size);
-        let mut shapes_const = Vec::with_capacity(total_size);
-        for (referer_id, (id, shape)) in slide.shapes.into_iter().rev().enumerate() {
-            match shape {
-                Shape::Shape {
-                    state: ShapeState { x, y, w, h, color },
-                    ..
-                } => {
-                    refs[id] = referer_id;
-                    shapes_dyn.set(referer_id, true, target == (x, y));
-                    shapes_const.push(ShapeConstState { color, x, y, w, h });
-                }
-                Shape::Group { .. } => unimplemented!("groups"),
-            };
-        }
+// A machine playing a program, cloning it only copies the state. The cells of
+// its filters are words of type W, so that it can be tuned to the target
+#[derive(Clone)]
+pub struct Presentation<W: Word = Cell> {
+    pub program: Arc<Program<W>>,
+    pub state: State<W>,
+    pub profile: Option<Profile>,
+    // visibility cells before the last click group with masks, to hash the
+    // ones it changed
+    scratch: Vec<W>,
+}
+
+impl<W: Word> Presentation<W> {
+    pub fn try_from(slide: Slide, target: (f32, f32)) -> Result<Self> {
+        let (program, state) = compile(slide, target)?;
+        Ok(Presentation::new(Arc::new(program), state))
+    }
+
+    // Machine playing the program from the state, hashed from scratch
+    pub fn new(program: Arc<Program<W>>, state: State<W>) -> Self {
+        let mut presentation = Self {
+            program,
+            state,
+            profile: None,
+            scratch: Vec::new(),
+        };
+        presentation.state.hash = presentation.rehash();
+        presentation
+    }
+}
 
-        let len_full = slide
+fn compile<W: Word>(mut slide: Slide, target: (f32, f32)) -> Result<(Program<W>, State<W>)> {
+    slide.check_referers()?;
+    slide.shapes.sort_by_key(|a| a.1.z());
+    let total_size = slide.shapes.iter().map(|e| e.1.size()).sum();
+    let mut report = CompileReport {
+        shapes: slide.shapes.len(),
+        flattened: total_size,
+        contexts: slide
             .timeline
             .contexts
             .iter()
-            .chain([&slide.timeline.main_context])
-            .map(|c| c.animations.len())
-            .sum::<usize>();
-        let mut main_context = slide.timeline.main_context;
-        let main_context = init_context(
-            &mut main_context,
-            &refs,
-            &mut shapes_dyn,
-            &mut shapes_const,
-            target,
-        );
-        let mut contexts = vec![BasicContext::default(); total_size];
-        for (id, mut context) in slide.timeline.contexts.into_iter().enumerate() {
-            contexts[refs[id]] = init_context(
-                &mut context,
-                &refs,
-                &mut shapes_dyn,
-                &mut shapes_const,
-                target,
-            );
-        }
-        let len_basic = contexts
-            .iter()
-            .chain([&main_context])
-            .map(|c| c.animations.iter().map(|s| s.len()).sum::<usize>())
-            .sum::<usize>();
-        println!("{len_full} {len_basic}");
+            .filter(|c| !c.animations.is_empty())
+            .count(),
+        animations: slide.timeline.animations().count(),
+        ..CompileReport::default()
+    };
+    let mut refs = vec![0; slide.shapes.len()];
+    let mut targets = slide.hot_points.clone();
+    if !targets.contains(&target) {
+        targets.push(target);
+    }
+    let mut visible = Filter::new(total_size);
+    let mut planes = vec![Filter::new(total_size); targets.len()];
+    let mut shapes_const = Vec::with_capacity(total_size);
+    let mut lattice = Vec::with_capacity(total_size);
+    let mut names = Vec::with_capacity(total_size);
+    for (referer_id, (id, shape)) in slide.shapes.into_iter().rev().enumerate() {
+        match shape {
+            Shape::Shape {
+                name,
+                state: ShapeState { x, y, w, h, color },
+                ..
+            } => {
+                refs[id] = referer_id;
+                visible.set(referer_id);
+                for (plane, &(tx, ty)) in planes.iter_mut().zip(&targets) {
+                    if covers((x, y, w, h), tx, ty) {
+                        plane.set(referer_id);
+                    }
+                }
+                lattice.push(vec![(x, y)]);
+                names.push(name);
+                shapes_const.push(ShapeConstState { color, x, y, w, h });
+            }
+            Shape::Group { .. } => return Err(Error::UnsupportedShape { shape: id }),
+        };
+    }
 
-        Presentation {
-            timeline: BasicTimeline {
-                main_context,
-                contexts,
-            },
-            states_dyn: shapes_dyn,
-            states_const: shapes_const,
-            width: slide.width,
-            height: slide.height,
-            iters: 0,
-            target,
+    let mut initialised = Filter::new(total_size);
+    let mut main_context = slide.timeline.main_context;
+    let mut main_context = init_context(
+        &mut main_context,
+        &refs,
+        &mut visible,
+        &mut initialised,
+        &shapes_const,
+        &mut lattice,
+        &targets,
+    )?;
+    let mut contexts = vec![BasicContext::default(); total_size];
+    for (id, mut context) in slide.timeline.contexts.into_iter().enumerate() {
+        if context.animations.is_empty() {
+            continue;
         }
+        let referer = *refs.get(id).ok_or(Error::DanglingReferer {
+            referer: id,
+            shapes: refs.len(),
+        })?;
+        contexts[referer] = init_context(
+            &mut context,
+            &refs,
+            &mut visible,
+            &mut initialised,
+            &shapes_const,
+            &mut lattice,
+            &targets,
+        )?;
     }
+    let cells = visible.cells.len();
+    for context in contexts.iter_mut().chain([&mut main_context]) {
+        context.masks = context
+            .animations
+            .iter()
+            .map(|group| (group.len() * DENSE >= cells).then(|| Box::new(masks(group, cells))))
+            .collect();
+    }
+    let groups = contexts
+        .iter()
+        .chain([&main_context])
+        .map(|c| &c.animations);
+    report.merged = groups.clone().flatten().map(Vec::len).sum();
+    report.groups = groups.clone().map(Vec::len).collect();
+    let cells = groups.flatten().flatten().map(|animation| animation.cell);
+    report.cells = cells.collect::<HashSet<_>>().len();
+
+    let state = State {
+        visible,
+        planes,
+        places: vec![0; lattice.len()],
+        heads: vec![0; contexts.len() + 1],
+        iters: 0,
+        hash: 0,
+    };
+    let mut program = Program {
+        timeline: BasicTimeline {
+            main_context,
+            contexts,
+        },
+        partition: None,
+        lattice,
+        states_const: shapes_const,
+        names,
+        width: slide.width,
+        height: slide.height,
+        targets,
+        show_end: ShowEnd::default(),
+        report,
+    };
+    program.report.memory = memory(&program, &state);
+    Ok((program, state))
 }
 
-impl fmt::Debug for Presentation {
+impl<W: Word> fmt::Debug for Presentation<W> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for state_const in &self.states_const {
+        for state_const in &self.program.states_const {
             f.write_fmt(format_args!(
                 "| {:04} {:04} {:04} {:04} ",
                 state_const.x, state_const.y, state_const.w, state_const.h
             ))?;
         }
-        for i in 0..self.states_const.len() {
-            let (visible, targeted) = self.states_dyn.get(i);
-            f.write_fmt(format_args!("| {visible:05} {targeted:05}         ",))?;
+        for i in 0..self.program.states_const.len() {
+            let visible = self.state.visible.get(i);
+            let place = self.state.places[i];
+            f.write_fmt(format_args!("| {visible:05} {place:05}         ",))?;
         }
         f.write_str("|\n")?;
         Ok(())
     }
 }
 
-impl Presentation {
+impl<W: Word> Presentation<W> {
+    pub fn on_slide(&self, x: f32, y: f32) -> bool {
+        in_bounds(x, y, self.program.width, self.program.height)
+    }
+
+    pub fn position(&self, index: usize) -> (f32, f32) {
+        self.program.lattice[index][self.state.places[index] as usize]
+    }
+
     pub fn under(&mut self, x: f32, y: f32) -> Option<usize> {
-        if self.target == (x, y) {
-            return self.states_dyn.last();
+        if !self.on_slide(x, y) {
+            return None;
+        }
+        let program = &*self.program;
+        let len = program.states_const.len();
+        if let Some(plane) = program.targets.iter().position(|target| *target == (x, y)) {
+            // counting the filter cells scanned from the top down to the hit
+            let visible = &self.state.visible;
+            let last = visible.last_and(&self.state.planes[plane]);
+            let cells = visible.cells();
+            self.state.iters += last.map_or(cells, |index| cells - (index >> W::SHIFT));
+            return last;
+        } else if let Some(partition) = &program.partition {
+            let candidates = partition.candidates(x, y).rev_iter();
+            for i in candidates {
+                self.state.iters += 1;
+                if !self.state.visible.get(i) {
+                    continue;
+                }
+                let (sx, sy) = self.position(i);
+                let ShapeConstState { w, h, .. } = program.states_const[i];
+                if covers((sx, sy, w, h), x, y) {
+                    return Some(i);
+                }
+            }
+            return None;
         } else {
-            for i in (0..self.states_const.len()).rev() {
-                let (visible, targeted) = self.states_dyn.get(i);
-                let state_const = &self.states_const[i];
-                if visible {
-                    let (sx, sy) = if targeted {
-                        self.target
-                    } else {
-                        (state_const.x, state_const.y)
-                    };
-                    if x >= sx && y >= sy && x <= sx + state_const.w && y <= sy + state_const.h {
-                        self.iters += self.states_const.len() - i;
+            for i in (0..len).rev() {
+                let state_const = &program.states_const[i];
+                if self.state.visible.get(i) {
+                    let (sx, sy) = self.position(i);
+                    if covers((sx, sy, state_const.w, state_const.h), x, y) {
+                        self.state.iters += len - i;
                         return Some(i);
                     }
                 }
             }
         }
-        self.iters += self.states_const.len();
+        self.state.iters += len;
         None
     }
 
-    pub fn click(&mut self, x: f32, y: f32) {
-        let (target, context) = match self.under(x, y) {
-            Some(referer) => {
-                let context = &mut self.timeline.contexts[referer];
-                if context.animations.is_empty() {
-                    (None, &mut self.timeline.main_context)
-                } else {
-                    (Some(referer), context)
-                }
+    // Hashes the whole state from scratch
+    pub fn rehash(&self) -> u64 {
+        let cells = self.state.visible.cells.iter().enumerate();
+        let places = self.state.places.iter().enumerate();
+        let heads = self.state.heads.iter().enumerate();
+        cells.fold(0, |hash, (i, cell)| {
+            hash ^ hash::cell(hash::VISIBLE, i, *cell)
+        }) ^ places.fold(0, |hash, (i, place)| {
+            hash ^ hash::key(hash::PLACE, i, *place as u64)
+        }) ^ heads.fold(0, |hash, (i, head)| {
+            hash ^ hash::key(hash::HEAD, i, *head as u64)
+        })
+    }
+
+    pub fn click(&mut self, x: f32, y: f32) -> Result<Outcome> {
+        if !self.on_slide(x, y) {
+            return Ok(Outcome::Missed);
+        }
+        let iters = self.state.iters;
+        let target = self.under(x, y);
+        let start = self.profile.is_some().then(Instant::now);
+        let (program, state, scratch) = (&*self.program, &mut self.state, &mut self.scratch);
+        let contexts = &program.timeline.contexts;
+        let (target, context) = match target {
+            Some(referer) if !contexts[referer].animations.is_empty() => {
+                (Some(referer), &contexts[referer])
             }
-            None => (None, &mut self.timeline.main_context),
+            _ => (None, &program.timeline.main_context),
         };
-        context.head = if context.head == context.animations.len() {
-            if target.is_none() {
-                exit(1);
+        let index = target.unwrap_or(contexts.len());
+        let old_head = state.heads[index];
+        let head = if old_head == context.animations.len() {
+            if target.is_none() && program.show_end == ShowEnd::Stop {
+                return Ok(Outcome::EndOfShow);
             }
-            1
+            // an empty main sequence wraps around to its start
+            usize::from(!context.animations.is_empty())
         } else {
-            context.head + 1
+            old_head + 1
+        };
+        state.heads[index] = head;
+        state.hash ^= hash::key(hash::HEAD, index, old_head as u64)
+            ^ hash::key(hash::HEAD, index, head as u64);
+        let (group, sources, masks) = match head.checked_sub(1) {
+            Some(group) => (
+                &context.animations[group][..],
+                context.sources[group],
+                context.masks[group].as_deref(),
+            ),
+            None => (&[][..], 0, None),
         };
-        for animation in &context.animations[context.head - 1] {
-            let cell = &mut self.states_dyn.cells[animation.cell];
-            cell.0 |= animation.visibility.set;
-            cell.0 &= !animation.visibility.unset;
-            cell.1 |= animation.targeting.set;
-            cell.1 &= !animation.targeting.unset;
+        if let Some(masks) = masks {
+            scratch.clone_from(&state.visible.cells);
+            let (set, unset) = &masks.visibility;
+            state.visible.apply(set, unset);
+            let cells = scratch.iter().zip(&state.visible.cells).enumerate();
+            for (index, (old, new)) in cells.filter(|(_, (old, new))| old != new) {
+                state.hash ^=
+                    hash::cell(hash::VISIBLE, index, *old) ^ hash::cell(hash::VISIBLE, index, *new);
+            }
+            for (plane, (set, unset)) in state.planes.iter_mut().zip(&masks.targeting) {
+                plane.apply(set, unset);
+            }
+        }
+        for animation in group {
+            if masks.is_none() {
+                let cell = &mut state.visible.cells[animation.cell];
+                let old = *cell;
+                *cell |= animation.visibility.set;
+                *cell &= !animation.visibility.unset;
+                if old != *cell {
+                    state.hash ^= hash::cell(hash::VISIBLE, animation.cell, old)
+                        ^ hash::cell(hash::VISIBLE, animation.cell, *cell);
+                }
+                for (plane, targeting) in state.planes.iter_mut().zip(&animation.targeting) {
+                    let cell = &mut plane.cells[animation.cell];
+                    *cell |= targeting.set;
+                    *cell &= !targeting.unset;
+                }
+            }
+            for &(shape, place) in &animation.moves {
+                if state.places[shape] != place {
+                    state.hash ^= hash::key(hash::PLACE, shape, state.places[shape] as u64)
+                        ^ hash::key(hash::PLACE, shape, place as u64);
+                    state.places[shape] = place;
+                }
+            }
+        }
+        if let (Some(profile), Some(start)) = (&mut self.profile, start) {
+            let counts = (sources, group.len(), state.iters - iters);
+            profile.record(index, head.saturating_sub(1), counts, start.elapsed());
         }
+        Ok(Outcome::Fired {
+            trigger: Trigger::from(target),
+            animations: sources,
+        })
     }
 
     pub fn render(&self, scale: f32, background: Color) -> Canvas<Color> {
-        let width = (self.width * scale) as usize;
-        let height = (self.height * scale) as usize;
+        let width = (self.program.width * scale) as usize;
+        let height = (self.program.height * scale) as usize;
         let mut canvas = Canvas::new(width, height, background);
-        for i in 0..self.states_const.len() {
-            let (visible, targeted) = self.states_dyn.get(i);
-            if visible {
-                let ShapeConstState { color, x, y, w, h } = self.states_const[i];
-                let (x, y) = if targeted { self.target } else { (x, y) };
+        for i in 0..self.program.states_const.len() {
+            if self.state.visible.get(i) {
+                let ShapeConstState { color, w, h, .. } = self.program.states_const[i];
+                let (x, y) = self.position(i);
                 let x = (x * scale + 0.5) as isize;
                 let y = (y * scale + 0.5) as isize;
                 let w = (w * scale + 0.5) as isize;
@@ -230,31 +464,195 @@ impl Presentation {
     }
 }
 
-pub fn init_context(
+impl<W: Word> Runner for Presentation<W> {
+    const KIND: RunnerKind = RunnerKind::Minimal;
+    type Snapshot = (Filter<W>, Vec<u32>, Vec<usize>);
+
+    fn build(slide: Slide, config: Config) -> Result<Self> {
+        let partition =
+            (config.hit_test == HitTest::Partition).then(|| Partition::from_slide(&slide));
+        let (mut program, state) = compile(slide, config.target)?;
+        program.partition = partition;
+        program.show_end = config.show_end;
+        let mut presentation = Presentation::new(Arc::new(program), state);
+        if config.profile {
+            let contexts = presentation.program.timeline.contexts.len();
+            presentation.profile = Some(Profile::new(contexts));
+        }
+        Ok(presentation)
+    }
+    fn click(&mut self, x: f32, y: f32) -> Result<Outcome> {
+        Presentation::click(self, x, y)
+    }
+    fn under(&mut self, x: f32, y: f32) -> Option<usize> {
+        Presentation::under(self, x, y)
+    }
+    fn render(&self, scale: f32, background: Color) -> Canvas<Color> {
+        Presentation::render(self, scale, background)
+    }
+    fn iters(&self) -> usize {
+        self.state.iters
+    }
+    fn set_iters(&mut self, iters: usize) {
+        self.state.iters = iters;
+    }
+    fn len(&self) -> usize {
+        self.program.states_const.len()
+    }
+    fn shape(&self, index: usize) -> ShapeView {
+        let ShapeConstState { w, h, color, .. } = self.program.states_const[index];
+        let (x, y) = self.position(index);
+        ShapeView {
+            visible: self.state.visible.get(index),
+            x,
+            y,
+            w,
+            h,
+            color,
+        }
+    }
+    fn name(&self, index: usize) -> &'static str {
+        self.program.names[index]
+    }
+    fn state_hash(&self) -> u64 {
+        self.state.hash
+    }
+    // the planes follow from the places
+    fn snapshot(&self) -> Self::Snapshot {
+        let state = &self.state;
+        (
+            state.visible.clone(),
+            state.places.clone(),
+            state.heads.clone(),
+        )
+    }
+    fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+}
+
+// Bytes held by the program and the state, the partition aside
+fn memory<W: Word>(program: &Program<W>, state: &State<W>) -> usize {
+    let animation = |animation: &BasicAnimation<W>| {
+        size_of::<BasicAnimation<W>>()
+            + animation.targeting.len() * size_of::<CellOp<W>>()
+            + animation.moves.len() * size_of::<(usize, u32)>()
+    };
+    let masks = |masks: &Option<Box<Masks<W>>>| {
+        size_of::<Option<Box<Masks<W>>>>()
+            + masks.as_ref().map_or(0, |masks| {
+                size_of::<Masks<W>>()
+                    + masks.targeting.len() * size_of::<(Filter<W>, Filter<W>)>()
+                    + (1 + masks.targeting.len())
+                        * 2
+                        * masks.visibility.0.cells.len()
+                        * size_of::<W>()
+            })
+    };
+    let timeline = &program.timeline;
+    let contexts = timeline.contexts.iter().chain([&timeline.main_context]);
+    let contexts = contexts.map(|context| {
+        size_of::<BasicContext<W>>()
+            + context
+                .animations
+                .iter()
+                .map(|group| {
+                    size_of::<Vec<BasicAnimation<W>>>() + group.iter().map(animation).sum::<usize>()
+                })
+                .sum::<usize>()
+            + context.masks.iter().map(masks).sum::<usize>()
+    });
+    let lattice = program
+        .lattice
+        .iter()
+        .map(|positions| size_of::<Vec<(f32, f32)>>() + positions.len() * size_of::<(f32, f32)>());
+    let planes = state
+        .planes
+        .iter()
+        .map(|plane| plane.cells.len() * size_of::<W>());
+    size_of::<Program<W>>()
+        + size_of::<State<W>>()
+        + contexts.sum::<usize>()
+        + lattice.sum::<usize>()
+        + program.states_const.len() * size_of::<ShapeConstState>()
+        + program.names.len() * size_of::<&str>()
+        + program.targets.len() * size_of::<(f32, f32)>()
+        + state.visible.cells.len() * size_of::<W>()
+        + planes.sum::<usize>()
+        + state.places.len() * size_of::<u32>()
+        + state.heads.len() * size_of::<usize>()
+}
+
+fn covers((sx, sy, w, h): (f32, f32, f32, f32), x: f32, y: f32) -> bool {
+    x >= sx && y >= sy && x <= sx + w && y <= sy + h
+}
+
+pub fn init_context<W: Word>(
     context: &mut Context,
     refs: &[usize],
-    shapes_dyn: &mut DoubleFilter,
-    shapes_const: &mut [ShapeConstState],
-    target_xy: (f32, f32),
-) -> BasicContext {
+    visible: &mut Filter<W>,
+    initialised: &mut Filter<W>,
+    shapes_const: &[ShapeConstState],
+    lattice: &mut [Vec<(f32, f32)>],
+    targets: &[(f32, f32)],
+) -> Result<BasicContext<W>> {
     let mut animations = vec![];
+    let mut sources = vec![];
     let mut cells = HashMap::new();
-    for animation in context.animations.iter_mut() {
-        if animation.click {
-            let sequence = cells.into_values().collect::<Vec<_>>();
-            cells = HashMap::new();
-            animations.push(sequence);
+    for (index, animation) in context.animations.iter_mut().enumerate() {
+        // like the basic runner, the click flag of the first animation is ignored
+        if index == 0 || animation.click {
+            if index > 0 {
+                animations.push(cells.into_values().collect::<Vec<_>>());
+                cells = HashMap::new();
+            }
+            sources.push(0);
         }
+        *sources.last_mut().unwrap() += 1;
         let old_index = animation.target.index();
-        let target = refs[old_index];
-        let index = target >> CELL_SHIFT;
-        let sub_index = 1 << (target & CELL_MASK);
+        let target = *refs.get(old_index).ok_or(Error::DanglingReferer {
+            referer: old_index,
+            shapes: refs.len(),
+        })?;
+        // like the basic runner, a shape first seen entering starts hidden
+        if !initialised.get(target) {
+            initialised.set(target);
+            if matches!(animation.effect.preset(), Preset::Entr(_, _)) {
+                visible.unset(target);
+            }
+        }
+        let index = target >> W::SHIFT;
+        let sub_index = W::bit(target);
         let effect = cells.entry(index).or_insert(BasicAnimation {
             cell: index,
             visibility: CellOp::default(),
-            targeting: CellOp::default(),
+            targeting: vec![CellOp::default(); targets.len()],
+            moves: Vec::new(),
         });
-        let (ox, oy) = (shapes_const[target].x, shapes_const[target].y);
+        let ShapeConstState {
+            x: ox, y: oy, w, h, ..
+        } = shapes_const[target];
+        // move the shape to its destination and update the planes it covers
+        let mut park = |destination: (f32, f32)| {
+            let positions = &mut lattice[target];
+            let place = match positions.iter().position(|p| *p == destination) {
+                Some(place) => place,
+                None => {
+                    positions.push(destination);
+                    positions.len() - 1
+                }
+            };
+            effect.moves.retain(|(shape, _)| *shape != target);
+            effect.moves.push((target, place as u32));
+            let (x, y) = destination;
+            for (&(tx, ty), targeting) in targets.iter().zip(&mut effect.targeting) {
+                if covers((x, y, w, h), tx, ty) {
+                    targeting.set(sub_index);
+                } else {
+                    targeting.unset(sub_index);
+                }
+            }
+        };
         match &animation.effect {
             Effect::Appear => {
                 effect.visibility.set(sub_index);
@@ -263,19 +661,18 @@ pub fn init_context(
                 effect.visibility.unset(sub_index);
             }
             Effect::SlideIn { .. } => {
+                park((ox, oy));
                 effect.visibility.set(sub_index);
-                if target_xy == (ox, oy) {
-                    effect.targeting.set(sub_index);
-                } else {
-                    effect.targeting.unset(sub_index);
-                }
             }
             Effect::SlideOut { complete, .. } => {
                 if !*complete {
-                    unimplemented!("incomplete slideout");
+                    return Err(Error::UnsupportedEffect {
+                        effect: animation.effect.name(),
+                        shape: old_index,
+                    });
                 }
+                park((ox, oy));
                 effect.visibility.unset(sub_index);
-                effect.targeting.unset(sub_index);
             }
             Effect::Path { x, y, relative, .. } => {
                 let (x, y) = if *relative {
@@ -283,18 +680,47 @@ pub fn init_context(
                 } else {
                     (*x, *y)
                 };
-                if target_xy == (x, y) {
-                    effect.targeting.set(sub_index);
-                } else {
-                    effect.targeting.unset(sub_index);
-                }
+                park((x, y));
             }
         }
     }
-    let sequence = cells.into_values().collect::<Vec<_>>();
-    animations.push(sequence);
-    BasicContext {
-        head: 0,
+    if !sources.is_empty() {
+        animations.push(cells.into_values().collect::<Vec<_>>());
+    }
+    Ok(BasicContext {
         animations,
+        sources,
+        masks: Vec::new(),
+    })
+}
+
+fn masks<W: Word>(group: &[BasicAnimation<W>], cells: usize) -> Masks<W> {
+    let filters = || {
+        (
+            Filter {
+                cells: vec![W::ZERO; cells],
+            },
+            Filter {
+                cells: vec![W::ZERO; cells],
+            },
+        )
+    };
+    let planes = group
+        .first()
+        .map_or(0, |animation| animation.targeting.len());
+    let mut masks = Masks {
+        visibility: filters(),
+        targeting: vec![filters(); planes],
+    };
+    // a group holds one animation per cell
+    for animation in group {
+        let (set, unset) = &mut masks.visibility;
+        set.cells[animation.cell] = animation.visibility.set;
+        unset.cells[animation.cell] = animation.visibility.unset;
+        for ((set, unset), targeting) in masks.targeting.iter_mut().zip(&animation.targeting) {
+            set.cells[animation.cell] = targeting.set;
+            unset.cells[animation.cell] = targeting.unset;
+        }
     }
+    masks
 }
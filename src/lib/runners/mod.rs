@@ -1,2 +1,395 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{render::Canvas, Color, Effect, Result, Shape, Slide};
+use profile::{Hot, Profile};
+
 pub mod basic;
+pub mod batch;
+pub mod memo;
 pub mod minimal;
+pub mod profile;
+pub mod reference;
+pub mod sliced;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunnerKind {
+    Basic,
+    Minimal,
+    Reference,
+}
+
+impl RunnerKind {
+    pub fn supports_effect(&self, effect: &Effect) -> bool {
+        !matches!(
+            effect,
+            Effect::SlideOut {
+                complete: false,
+                ..
+            }
+        )
+    }
+    pub fn supports_shape(&self, shape: &Shape) -> bool {
+        match self {
+            RunnerKind::Basic | RunnerKind::Reference => true,
+            RunnerKind::Minimal => matches!(shape, Shape::Shape { .. }),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    Main,
+    Shape(usize),
+}
+
+impl From<Option<usize>> for Trigger {
+    fn from(shape: Option<usize>) -> Self {
+        shape.map_or(Trigger::Main, Trigger::Shape)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    // a click group of the main sequence or of a shape trigger ran
+    Fired { trigger: Trigger, animations: usize },
+    // the click landed outside the slide
+    Missed,
+    // the main sequence is exhausted and the show is not wrapping around
+    EndOfShow,
+}
+
+// What clicking past the last click group of the main sequence does,
+// Stop makes click return Outcome::EndOfShow
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShowEnd {
+    #[default]
+    Stop,
+    Wrap,
+}
+
+// How a runner finds the topmost shape under a click. The iteration counter
+// adds up what each strategy examines, shapes for all of them but Filter, so
+// only the strategies counting shapes compare with each other
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HitTest {
+    // scan every shape from the top, counting the shapes down to the hit
+    #[default]
+    Scan,
+    // resume the scan below the last hit while the same point is clicked,
+    // counting the shapes from where it resumed
+    Cache,
+    // keep a bitset of the visible shapes covering the last clicked point,
+    // counting the filter cells scanned from the top down to the hit
+    Filter,
+    // bucket the shapes in a uniform grid following them as they move,
+    // counting the candidates of the bucket checked
+    Grid,
+    // look the point up in the regions cut by every place a shape can be,
+    // built from the slide by Runner::build, counting the candidates of the
+    // region checked, hidden ones included
+    Partition,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Config {
+    // hot point of the runners with a targeted fast path, whose hit tests
+    // count the filter cells scanned from the top down to the hit
+    pub target: (f32, f32),
+    pub hit_test: HitTest,
+    pub show_end: ShowEnd,
+    // keep a profile::Profile of the clicks, for the runners supporting it
+    pub profile: bool,
+}
+
+// What compiling a slide produced, kept by the program of the runners that
+// compile one
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompileReport {
+    pub shapes: usize,
+    // shapes once the members of groups are laid out in place
+    pub flattened: usize,
+    // shape contexts with animations
+    pub contexts: usize,
+    // animations of the slide, then those the runner plays, the minimal
+    // runner merges the animations of a click group touching the same cell
+    pub animations: usize,
+    pub merged: usize,
+    // click groups of each shape context, then of the main one
+    pub groups: Vec<usize>,
+    // filter cells the animations touch
+    pub cells: usize,
+    // bytes held by the program and the initial state
+    pub memory: usize,
+}
+
+impl fmt::Display for CompileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "{} shapes ({} flattened), {} contexts, {} animations ({} merged), \
+             {} click groups, {} cells, {} bytes",
+            self.shapes,
+            self.flattened,
+            self.contexts,
+            self.animations,
+            self.merged,
+            self.groups.iter().sum::<usize>(),
+            self.cells,
+            self.memory
+        ))
+    }
+}
+
+// Observable state of a flattened shape, independent of the runner encoding
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShapeView {
+    pub visible: bool,
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+    pub color: Color,
+}
+
+pub trait Runner: Sized + Clone {
+    const KIND: RunnerKind;
+    // everything the next clicks depend on, snapshots of equal states are
+    // equal and those of different states are not
+    type Snapshot: PartialEq;
+
+    fn build(slide: Slide, config: Config) -> Result<Self>;
+    fn click(&mut self, x: f32, y: f32) -> Result<Outcome>;
+    // flattened index of the topmost shape under the point
+    fn under(&mut self, x: f32, y: f32) -> Option<usize>;
+    fn render(&self, scale: f32, background: Color) -> Canvas<Color>;
+    fn iters(&self) -> usize;
+    fn set_iters(&mut self, iters: usize);
+    fn reset_iters(&mut self) {
+        self.set_iters(0);
+    }
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn shape(&self, index: usize) -> ShapeView;
+    fn shapes(&self) -> Vec<ShapeView> {
+        (0..self.len()).map(|index| self.shape(index)).collect()
+    }
+    fn name(&self, index: usize) -> &'static str;
+    // hash of everything the next clicks depend on, equal states hash equally
+    fn state_hash(&self) -> u64;
+    fn snapshot(&self) -> Self::Snapshot;
+    fn profile(&self) -> Option<&Profile> {
+        None
+    }
+    // The n trigger contexts fired the most, if the runner keeps a profile
+    fn hottest(&self, n: usize) -> Vec<Hot> {
+        self.profile().map_or_else(Vec::new, |profile| {
+            profile.hottest(|index| self.name(index), n)
+        })
+    }
+    // Clicks at the point until one of the conditions holds, giving up after
+    // limit clicks
+    fn run(&mut self, point: (f32, f32), halts: &[Halt<Self>], limit: usize) -> Result<Run> {
+        click_through(self, point, halts, limit)
+    }
+}
+
+// Runner::run clicking the point one click at a time
+pub(crate) fn click_through<R: Runner>(
+    runner: &mut R,
+    (x, y): (f32, f32),
+    halts: &[Halt<R>],
+    limit: usize,
+) -> Result<Run> {
+    let mut clicks = 0;
+    // the runner as the run started, replayed to confirm hash matches
+    let origin = runner.clone();
+    // clicks after which each hash was seen
+    let mut seen = HashMap::<u64, Vec<usize>>::new();
+    let mut cycle = None;
+    loop {
+        let mut halt = None;
+        for (index, condition) in halts.iter().enumerate() {
+            let holds = match condition {
+                Halt::Topmost(name) => {
+                    // the click counts its own hit test
+                    let iters = runner.iters();
+                    let topmost = runner.under(x, y);
+                    runner.set_iters(iters);
+                    topmost.is_some_and(|index| runner.name(index) == *name)
+                }
+                Halt::EndOfShow => false,
+                Halt::Cycle => {
+                    let earlier = seen.entry(runner.state_hash()).or_default();
+                    match repeated(&origin, (x, y), earlier, runner)? {
+                        Some(start) => {
+                            cycle = Some(Cycle {
+                                start,
+                                length: clicks - start,
+                            });
+                            true
+                        }
+                        None => {
+                            earlier.push(clicks);
+                            false
+                        }
+                    }
+                }
+                Halt::Predicate(predicate) => predicate(runner),
+            };
+            if holds {
+                halt = Some(index);
+                break;
+            }
+        }
+        if halt.is_some() || clicks == limit {
+            return Ok(Run {
+                clicks,
+                halt,
+                cycle,
+            });
+        }
+        if runner.click(x, y)? == Outcome::EndOfShow {
+            let halt = halts
+                .iter()
+                .position(|halt| matches!(halt, Halt::EndOfShow));
+            if halt.is_some() {
+                return Ok(Run {
+                    clicks,
+                    halt,
+                    cycle,
+                });
+            }
+        }
+        clicks += 1;
+    }
+}
+
+// First of the earlier clicks after which the origin, clicked at the point,
+// is in the state of the runner. A hash match is only a cycle if the states
+// are equal
+fn repeated<R: Runner>(
+    origin: &R,
+    (x, y): (f32, f32),
+    earlier: &[usize],
+    runner: &R,
+) -> Result<Option<usize>> {
+    if earlier.is_empty() {
+        return Ok(None);
+    }
+    let snapshot = runner.snapshot();
+    let mut replay = origin.clone();
+    let mut clicks = 0;
+    for &click in earlier {
+        while clicks < click {
+            replay.click(x, y)?;
+            clicks += 1;
+        }
+        if replay.snapshot() == snapshot {
+            return Ok(Some(click));
+        }
+    }
+    Ok(None)
+}
+
+// Condition stopping Runner::run, checked before every click
+pub enum Halt<R> {
+    // the shape of that name is the topmost one under the clicked point
+    Topmost(&'static str),
+    // the main sequence is over, the click finding it out is not counted
+    EndOfShow,
+    // the state repeats one seen after an earlier click. The run keeps a copy
+    // of the runner as it started and a hash and a click count per click, a
+    // hash match is confirmed by replaying the copy up to the earlier click
+    Cycle,
+    Predicate(Box<dyn Fn(&R) -> bool + Send + Sync>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cycle {
+    // clicks played before the state first showed up
+    pub start: usize,
+    pub length: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Run {
+    pub clicks: usize,
+    // index of the condition that held, None if the click limit was reached
+    pub halt: Option<usize>,
+    pub cycle: Option<Cycle>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counter going round a period of clicks, whose hash only keeps its
+    // parity so that different states collide
+    #[derive(Clone)]
+    struct Counter {
+        value: usize,
+        period: usize,
+    }
+
+    impl Runner for Counter {
+        const KIND: RunnerKind = RunnerKind::Basic;
+        type Snapshot = usize;
+
+        fn build(_: Slide, _: Config) -> Result<Self> {
+            Ok(Self {
+                value: 0,
+                period: 3,
+            })
+        }
+        fn click(&mut self, _: f32, _: f32) -> Result<Outcome> {
+            self.value = (self.value + 1) % self.period;
+            Ok(Outcome::Fired {
+                trigger: Trigger::Main,
+                animations: 1,
+            })
+        }
+        fn under(&mut self, _: f32, _: f32) -> Option<usize> {
+            None
+        }
+        fn render(&self, _: f32, background: Color) -> Canvas<Color> {
+            Canvas::new(0, 0, background)
+        }
+        fn iters(&self) -> usize {
+            0
+        }
+        fn set_iters(&mut self, _: usize) {}
+        fn len(&self) -> usize {
+            0
+        }
+        fn shape(&self, _: usize) -> ShapeView {
+            unreachable!()
+        }
+        fn name(&self, _: usize) -> &'static str {
+            ""
+        }
+        fn state_hash(&self) -> u64 {
+            self.value as u64 % 2
+        }
+        fn snapshot(&self) -> usize {
+            self.value
+        }
+    }
+
+    #[test]
+    fn cycle_needs_equal_states() {
+        let mut counter = Counter::build(Slide::new(1., 1.), Config::default()).unwrap();
+        let run = counter.run((0., 0.), &[Halt::Cycle], 10);
+        assert_eq!(
+            run,
+            Ok(Run {
+                clicks: 3,
+                halt: Some(0),
+                cycle: Some(Cycle {
+                    start: 0,
+                    length: 3
+                }),
+            })
+        );
+    }
+}
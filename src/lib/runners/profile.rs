@@ -0,0 +1,105 @@
+// Counters of the basic and minimal runners kept per trigger context, enabled
+// by Config::profile. They only cost something when enabled.
+
+use std::{fmt, time::Duration};
+
+use super::Trigger;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub fired: usize,
+    // animations of the slide played, and filter cells they changed
+    pub animations: usize,
+    pub cells: usize,
+    // shapes or filter cells the hit tests examined on the clicks firing it
+    pub scanned: usize,
+    // time spent playing each click group
+    pub groups: Vec<Duration>,
+}
+
+impl Stats {
+    pub fn time(&self) -> Duration {
+        self.groups.iter().sum()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Profile {
+    // stats of each shape context, then of the main one
+    pub contexts: Vec<Stats>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Hot {
+    pub trigger: Trigger,
+    pub name: &'static str,
+    pub stats: Stats,
+}
+
+impl Profile {
+    pub fn new(contexts: usize) -> Self {
+        Self {
+            contexts: vec![Stats::default(); contexts + 1],
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        context: usize,
+        group: usize,
+        (animations, cells, scanned): (usize, usize, usize),
+        time: Duration,
+    ) {
+        let stats = &mut self.contexts[context];
+        stats.fired += 1;
+        stats.animations += animations;
+        stats.cells += cells;
+        stats.scanned += scanned;
+        if stats.groups.len() <= group {
+            stats.groups.resize(group + 1, Duration::ZERO);
+        }
+        stats.groups[group] += time;
+    }
+
+    // The n contexts fired the most, the longest to play first among equals,
+    // named after their trigger shape
+    pub fn hottest(&self, name: impl Fn(usize) -> &'static str, n: usize) -> Vec<Hot> {
+        let main = self.contexts.len() - 1;
+        let mut fired = self
+            .contexts
+            .iter()
+            .enumerate()
+            .filter(|(_, stats)| stats.fired > 0)
+            .collect::<Vec<_>>();
+        fired.sort_by(|(_, a), (_, b)| b.fired.cmp(&a.fired).then(b.time().cmp(&a.time())));
+        fired
+            .into_iter()
+            .take(n)
+            .map(|(index, stats)| Hot {
+                trigger: Trigger::from((index != main).then_some(index)),
+                name: if index == main { "" } else { name(index) },
+                stats: stats.clone(),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Hot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stats = &self.stats;
+        let trigger = match self.trigger {
+            Trigger::Main => "main".to_string(),
+            Trigger::Shape(index) => format!("shape {index}"),
+        };
+        f.write_fmt(format_args!(
+            "{trigger:>12} {:<12} fired {:>8}, {:>9} animations, {:>9} cells, \
+             {:>9} scanned, {:>10.1?}",
+            self.name,
+            stats.fired,
+            stats.animations,
+            stats.cells,
+            stats.scanned,
+            stats.time()
+        ))
+    }
+}
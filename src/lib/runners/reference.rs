@@ -0,0 +1,371 @@
+// Slow runner meant to be obviously correct and as close as possible to what
+// PowerPoint does. Its semantic choices:
+//
+// - Shapes are laid out like the other runners: slide shapes by decreasing Z,
+//   group members flattened in place, the last shape being the topmost.
+// - A shape whose first effect is an entrance starts hidden, every other shape
+//   starts visible. Effects are visited main sequence first, then the trigger
+//   sequences in shape order.
+// - Motion paths accumulate: each Path moves the shape by its offset from
+//   wherever it currently is. A Target is recorded as the offset from the home
+//   position to the destination, as PowerPoint stores paths relative to the
+//   shape, so replaying it moves the shape further.
+// - Fly in (SlideIn) and fly out (SlideOut) animate along the original layout
+//   and leave the shape at its home position, discarding accumulated motion.
+// - A click reaches the topmost visible shape containing it, edges included,
+//   and only if it lands on the slide.
+// - Clicking a shape with a trigger sequence plays its next click group, and
+//   restarts the sequence once it is exhausted. Any other click plays the next
+//   click group of the main sequence, which either ends the show or wraps.
+// - The click flag of the first animation of a sequence is ignored, the first
+//   click always plays it.
+
+use std::fmt;
+
+use super::{Config, Outcome, Runner, RunnerKind, ShapeView, ShowEnd, Trigger};
+use crate::{
+    error::{Error, Result},
+    hash, in_bounds,
+    render::Canvas,
+    Color, Effect, Preset, Referer, Shape, ShapeState, Slide,
+};
+
+#[derive(Clone, Debug)]
+pub struct ShapeRef {
+    pub name: &'static str,
+    pub home: (f32, f32),
+    pub state: ShapeState,
+    pub visible: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Step {
+    Show,
+    Hide,
+    Move(f32, f32),
+    Home,
+    HideHome,
+}
+
+#[derive(Clone, Debug)]
+pub struct Sequence {
+    pub head: usize,
+    // click groups of (first shape, end shape, step)
+    pub groups: Vec<Vec<(usize, usize, Step)>>,
+}
+
+#[derive(Clone)]
+pub struct Presentation {
+    pub shapes: Vec<ShapeRef>,
+    // flattened index of the slide shape each flattened shape belongs to
+    pub owners: Vec<usize>,
+    pub main: Sequence,
+    pub triggers: Vec<Option<Sequence>>,
+    pub width: f32,
+    pub height: f32,
+    pub iters: usize,
+    pub show_end: ShowEnd,
+}
+
+pub(crate) fn flatten(shape: Shape, out: &mut Vec<(&'static str, ShapeState)>) {
+    let mut queue = match shape {
+        Shape::Shape { name, state, .. } => {
+            out.push((name, state));
+            return;
+        }
+        Shape::Group { mut shapes, .. } => {
+            shapes.sort_by_key(|a| std::cmp::Reverse(a.z()));
+            shapes
+        }
+    };
+    while let Some(shape) = queue.pop() {
+        match shape {
+            Shape::Shape { name, state, .. } => out.push((name, state)),
+            Shape::Group { mut shapes, .. } => {
+                shapes.sort_by_key(|a| std::cmp::Reverse(a.z()));
+                queue = [queue, shapes].concat();
+            }
+        }
+    }
+}
+
+fn compile(
+    animations: &[crate::Animation],
+    refs: &[Referer],
+    shapes: &mut [ShapeRef],
+    seen: &mut [bool],
+) -> Result<Sequence> {
+    let mut groups: Vec<Vec<_>> = Vec::new();
+    for (index, animation) in animations.iter().enumerate() {
+        let id = animation.target.index();
+        let referer = *refs.get(id).ok_or(Error::DanglingReferer {
+            referer: id,
+            shapes: refs.len(),
+        })?;
+        let (start, end) = referer.bounds();
+        let (hx, hy) = shapes[start..end]
+            .iter()
+            .fold((f32::MAX, f32::MAX), |(x, y), shape| {
+                (x.min(shape.home.0), y.min(shape.home.1))
+            });
+        let step = match animation.effect {
+            Effect::Appear => Step::Show,
+            Effect::Disappear => Step::Hide,
+            Effect::SlideIn { .. } => Step::Home,
+            Effect::SlideOut { complete: true, .. } => Step::HideHome,
+            Effect::Path {
+                x,
+                y,
+                relative: true,
+                ..
+            } => Step::Move(x, y),
+            Effect::Path {
+                x,
+                y,
+                relative: false,
+                ..
+            } => Step::Move(x - hx, y - hy),
+            Effect::SlideOut { .. } => {
+                return Err(Error::UnsupportedEffect {
+                    effect: animation.effect.name(),
+                    shape: id,
+                })
+            }
+        };
+        for i in start..end {
+            if !seen[i] {
+                seen[i] = true;
+                shapes[i].visible = !matches!(animation.effect.preset(), Preset::Entr(_, _));
+            }
+        }
+        if index == 0 || animation.click {
+            groups.push(Vec::new());
+        }
+        groups.last_mut().unwrap().push((start, end, step));
+    }
+    Ok(Sequence { head: 0, groups })
+}
+
+impl TryFrom<Slide> for Presentation {
+    type Error = Error;
+
+    fn try_from(mut slide: Slide) -> Result<Self> {
+        slide.check_referers()?;
+        slide.shapes.sort_by_key(|a| a.1.z());
+        let mut refs = vec![Referer::Shape(0); slide.shapes.len()];
+        let mut flat = Vec::new();
+        let mut owners = Vec::new();
+        for (id, shape) in slide.shapes.into_iter().rev() {
+            let start = flat.len();
+            flatten(shape, &mut flat);
+            let size = flat.len() - start;
+            refs[id] = if size == 1 {
+                Referer::Shape(start)
+            } else {
+                Referer::Group(start, size)
+            };
+            owners.extend(std::iter::repeat_n(start, size));
+        }
+        let mut shapes = flat
+            .into_iter()
+            .map(|(name, state)| ShapeRef {
+                name,
+                home: (state.x, state.y),
+                state,
+                visible: true,
+            })
+            .collect::<Vec<_>>();
+
+        let mut seen = vec![false; shapes.len()];
+        let main = compile(
+            &slide.timeline.main_context.animations,
+            &refs,
+            &mut shapes,
+            &mut seen,
+        )?;
+        let mut triggers = vec![None; shapes.len()];
+        for (id, context) in slide.timeline.contexts.iter().enumerate() {
+            if context.animations.is_empty() {
+                continue;
+            }
+            let referer = *refs.get(id).ok_or(Error::DanglingReferer {
+                referer: id,
+                shapes: refs.len(),
+            })?;
+            triggers[referer.index()] =
+                Some(compile(&context.animations, &refs, &mut shapes, &mut seen)?);
+        }
+
+        Ok(Presentation {
+            shapes,
+            owners,
+            main,
+            triggers,
+            width: slide.width,
+            height: slide.height,
+            iters: 0,
+            show_end: ShowEnd::default(),
+        })
+    }
+}
+
+impl fmt::Debug for Presentation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, shape) in self.shapes.iter().enumerate() {
+            f.write_fmt(format_args!(
+                "{index:05} {:5} {:?} {:?}\n",
+                shape.visible, shape.state, shape.name
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+impl Presentation {
+    pub fn under(&mut self, x: f32, y: f32) -> Option<usize> {
+        if !in_bounds(x, y, self.width, self.height) {
+            return None;
+        }
+        for (index, shape) in self.shapes.iter().enumerate().rev() {
+            self.iters += 1;
+            let ShapeState {
+                x: sx, y: sy, w, h, ..
+            } = shape.state;
+            if shape.visible && x >= sx && y >= sy && x <= sx + w && y <= sy + h {
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    pub fn click(&mut self, x: f32, y: f32) -> Result<Outcome> {
+        if !in_bounds(x, y, self.width, self.height) {
+            return Ok(Outcome::Missed);
+        }
+        let owner = self
+            .under(x, y)
+            .map(|index| self.owners[index])
+            .filter(|owner| self.triggers[*owner].is_some());
+        let sequence = match owner {
+            Some(owner) => self.triggers[owner].as_mut().unwrap(),
+            None => &mut self.main,
+        };
+        if sequence.head == sequence.groups.len() {
+            if owner.is_none() && self.show_end == ShowEnd::Stop {
+                return Ok(Outcome::EndOfShow);
+            }
+            sequence.head = 0;
+        }
+        // an empty main sequence wraps around to nothing
+        let Some(group) = sequence.groups.get(sequence.head) else {
+            return Ok(Outcome::Fired {
+                trigger: Trigger::Main,
+                animations: 0,
+            });
+        };
+        sequence.head += 1;
+        for &(start, end, step) in group {
+            for shape in &mut self.shapes[start..end] {
+                match step {
+                    Step::Show => shape.visible = true,
+                    Step::Hide => shape.visible = false,
+                    Step::Move(dx, dy) => {
+                        shape.state.x += dx;
+                        shape.state.y += dy;
+                    }
+                    Step::Home | Step::HideHome => {
+                        shape.visible = matches!(step, Step::Home);
+                        (shape.state.x, shape.state.y) = shape.home;
+                    }
+                }
+            }
+        }
+        Ok(Outcome::Fired {
+            trigger: Trigger::from(owner),
+            animations: group.len(),
+        })
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        let mut state = hash::key(hash::HEAD, self.shapes.len(), self.main.head as u64);
+        for (i, shape) in self.shapes.iter().enumerate() {
+            state ^= hash::key(hash::SHAPE, i, hash::point(shape.state.x, shape.state.y))
+                ^ hash::key(hash::VISIBLE, i, shape.visible as u64);
+        }
+        for (i, sequence) in self.triggers.iter().enumerate() {
+            if let Some(sequence) = sequence {
+                state ^= hash::key(hash::HEAD, i, sequence.head as u64);
+            }
+        }
+        state
+    }
+
+    pub fn render(&self, scale: f32, background: Color) -> Canvas<Color> {
+        let width = (self.width * scale) as usize;
+        let height = (self.height * scale) as usize;
+        let mut canvas = Canvas::new(width, height, background);
+        for shape in self.shapes.iter().filter(|shape| shape.visible) {
+            let ShapeState { x, y, w, h, color } = shape.state;
+            let x = (x * scale + 0.5) as isize;
+            let y = (y * scale + 0.5) as isize;
+            let w = (w * scale + 0.5) as isize;
+            let h = (h * scale + 0.5) as isize;
+            canvas.fill_rect(x, y, w, h, color);
+        }
+        canvas
+    }
+}
+
+impl Runner for Presentation {
+    const KIND: RunnerKind = RunnerKind::Reference;
+    type Snapshot = (Vec<ShapeView>, Vec<Option<usize>>);
+
+    fn build(slide: Slide, config: Config) -> Result<Self> {
+        let mut presentation = Presentation::try_from(slide)?;
+        presentation.show_end = config.show_end;
+        Ok(presentation)
+    }
+    fn click(&mut self, x: f32, y: f32) -> Result<Outcome> {
+        Presentation::click(self, x, y)
+    }
+    fn under(&mut self, x: f32, y: f32) -> Option<usize> {
+        Presentation::under(self, x, y)
+    }
+    fn render(&self, scale: f32, background: Color) -> Canvas<Color> {
+        Presentation::render(self, scale, background)
+    }
+    fn iters(&self) -> usize {
+        self.iters
+    }
+    fn set_iters(&mut self, iters: usize) {
+        self.iters = iters;
+    }
+    fn len(&self) -> usize {
+        self.shapes.len()
+    }
+    fn shape(&self, index: usize) -> ShapeView {
+        let shape = &self.shapes[index];
+        ShapeView {
+            visible: shape.visible,
+            x: shape.state.x,
+            y: shape.state.y,
+            w: shape.state.w,
+            h: shape.state.h,
+            color: shape.state.color,
+        }
+    }
+    fn name(&self, index: usize) -> &'static str {
+        self.shapes[index].name
+    }
+    fn state_hash(&self) -> u64 {
+        Presentation::state_hash(self)
+    }
+    fn snapshot(&self) -> Self::Snapshot {
+        let heads = self
+            .triggers
+            .iter()
+            .map(|sequence| sequence.as_ref().map(|s| s.head));
+        let heads = heads.chain([Some(self.main.head)]).collect();
+        (self.shapes(), heads)
+    }
+}
@@ -0,0 +1,496 @@
+// Bit-sliced version of the minimal runner: every bit of a cell is a lane
+// running its own instance of the same slide. The state of a shape is a cell
+// per property holding that property for all the lanes, so a click is played
+// on every lane at once with masked operations.
+
+use super::{
+    minimal::{self, BasicContext, ShapeConstState},
+    Config, Outcome, Runner, RunnerKind, ShapeView, ShowEnd, Trigger,
+};
+use crate::{
+    filters::{Cell, CELL_SHIFT, CELL_SIZE},
+    hash, in_bounds,
+    render::Canvas,
+    Color, Result, Slide,
+};
+
+pub const LANES: usize = CELL_SIZE;
+pub const ALL: Cell = Cell::MAX;
+
+#[derive(Clone, Debug, Default)]
+pub struct Group {
+    pub shows: Vec<usize>,
+    pub hides: Vec<usize>,
+    pub moves: Vec<(usize, u32)>,
+    // animations of the slide the group was merged from
+    pub animations: usize,
+}
+
+// The click groups played by each lane, like the minimal runner heads, are
+// bit-sliced too: bit b of the head of a lane is its bit in the cell b
+#[derive(Clone, Debug)]
+pub struct Sequence {
+    pub groups: Vec<Group>,
+    pub heads: Vec<Cell>,
+}
+
+impl Sequence {
+    pub fn head(&self, lane: usize) -> u32 {
+        let bits = self.heads.iter().enumerate();
+        bits.fold(0, |head, (b, bits)| {
+            head | (((bits >> lane) & 1) as u32) << b
+        })
+    }
+    // The lanes among the given ones whose head is the given one
+    fn at(&self, head: u32, lanes: Cell) -> Cell {
+        let bits = self.heads.iter().enumerate();
+        bits.fold(lanes, |lanes, (b, bits)| match head & (1 << b) {
+            0 => lanes & !bits,
+            _ => lanes & bits,
+        })
+    }
+    fn set(&mut self, head: u32, lanes: Cell) {
+        for (b, bits) in self.heads.iter_mut().enumerate() {
+            match head & (1 << b) {
+                0 => *bits &= !lanes,
+                _ => *bits |= lanes,
+            }
+        }
+    }
+}
+
+// Shapes able to cover a point topmost first, with the places of their
+// lattice where they do
+type Cover = Vec<(usize, Vec<u32>)>;
+
+#[derive(Clone)]
+pub struct Presentation {
+    pub visible: Vec<Cell>,
+    // lanes where each shape is at each position of its lattice
+    pub at: Vec<Vec<Cell>>,
+    pub lattice: Vec<Vec<(f32, f32)>>,
+    pub sizes: Vec<(f32, f32)>,
+    pub colors: Vec<Color>,
+    pub names: Vec<&'static str>,
+    pub main: Sequence,
+    pub triggers: Vec<Option<Sequence>>,
+    // what covers each hot point, and the last other point clicked
+    pub targets: Vec<((f32, f32), Cover)>,
+    pub last: Option<((f32, f32), Cover)>,
+    pub width: f32,
+    pub height: f32,
+    pub iters: usize,
+    pub show_end: ShowEnd,
+}
+
+fn sequence(context: &BasicContext) -> Sequence {
+    let groups = context
+        .animations
+        .iter()
+        .zip(&context.sources)
+        .map(|(animations, sources)| {
+            let mut group = Group {
+                animations: *sources,
+                ..Group::default()
+            };
+            for animation in animations {
+                let base = animation.cell << CELL_SHIFT;
+                for bit in 0..CELL_SIZE {
+                    if animation.visibility.set & (1 << bit) != 0 {
+                        group.shows.push(base + bit);
+                    }
+                    if animation.visibility.unset & (1 << bit) != 0 {
+                        group.hides.push(base + bit);
+                    }
+                }
+                group.moves.extend(&animation.moves);
+            }
+            group
+        })
+        .collect::<Vec<_>>();
+    // enough bits for heads up to the number of groups
+    let bits = u32::BITS - (groups.len() as u32).leading_zeros();
+    Sequence {
+        groups,
+        heads: vec![0; bits as usize],
+    }
+}
+
+fn cover(lattice: &[Vec<(f32, f32)>], sizes: &[(f32, f32)], x: f32, y: f32) -> Cover {
+    (0..lattice.len())
+        .rev()
+        .filter_map(|i| {
+            let (w, h) = sizes[i];
+            let places = lattice[i]
+                .iter()
+                .enumerate()
+                .filter(|(_, (sx, sy))| x >= *sx && y >= *sy && x <= sx + w && y <= sy + h)
+                .map(|(place, _)| place as u32)
+                .collect::<Vec<_>>();
+            (!places.is_empty()).then_some((i, places))
+        })
+        .collect()
+}
+
+impl From<minimal::Presentation> for Presentation {
+    fn from(minimal: minimal::Presentation) -> Self {
+        let (program, state) = (&*minimal.program, &minimal.state);
+        let len = program.states_const.len();
+        let visible = (0..len)
+            .map(|i| if state.visible.get(i) { ALL } else { 0 })
+            .collect();
+        let at = program
+            .lattice
+            .iter()
+            .zip(&state.places)
+            .map(|(positions, place)| {
+                let mut at = vec![0; positions.len()];
+                at[*place as usize] = ALL;
+                at
+            })
+            .collect();
+        let triggers = program
+            .timeline
+            .contexts
+            .iter()
+            .map(|context| (!context.animations.is_empty()).then(|| sequence(context)))
+            .collect();
+        let sizes = program
+            .states_const
+            .iter()
+            .map(|ShapeConstState { w, h, .. }| (*w, *h))
+            .collect::<Vec<_>>();
+        let targets = program
+            .targets
+            .iter()
+            .map(|&(x, y)| ((x, y), cover(&program.lattice, &sizes, x, y)))
+            .collect();
+        Presentation {
+            visible,
+            at,
+            sizes,
+            colors: program.states_const.iter().map(|s| s.color).collect(),
+            lattice: program.lattice.clone(),
+            names: program.names.clone(),
+            main: sequence(&program.timeline.main_context),
+            triggers,
+            targets,
+            last: None,
+            width: program.width,
+            height: program.height,
+            iters: 0,
+            show_end: program.show_end,
+        }
+    }
+}
+
+impl Presentation {
+    pub fn build(slide: Slide, config: Config) -> Result<Self> {
+        Ok(Self::from(minimal::Presentation::build(slide, config)?))
+    }
+
+    // Topmost shape under the point for each of the lanes, as (shape, lanes)
+    // pairs from the top, the lanes left over hit nothing
+    pub fn under(&mut self, x: f32, y: f32, lanes: Cell) -> (Vec<(usize, Cell)>, Cell) {
+        let mut hits = Vec::new();
+        let mut remaining = lanes;
+        if !in_bounds(x, y, self.width, self.height) {
+            return (hits, remaining);
+        }
+        let cover = match self
+            .targets
+            .iter()
+            .position(|(target, _)| *target == (x, y))
+        {
+            Some(hot) => &self.targets[hot].1,
+            None => {
+                if !matches!(self.last, Some((point, _)) if point == (x, y)) {
+                    let cover = cover(&self.lattice, &self.sizes, x, y);
+                    self.last = Some(((x, y), cover));
+                }
+                &self.last.as_ref().unwrap().1
+            }
+        };
+        for (i, places) in cover {
+            if remaining == 0 {
+                break;
+            }
+            self.iters += 1;
+            let at = &self.at[*i];
+            let covering = places
+                .iter()
+                .fold(0, |lanes, place| lanes | at[*place as usize]);
+            let hit = self.visible[*i] & remaining & covering;
+            if hit != 0 {
+                hits.push((*i, hit));
+                remaining &= !hit;
+            }
+        }
+        (hits, remaining)
+    }
+
+    // Plays the next click group of the sequence on the lanes, returns the
+    // lanes whose show ends instead
+    fn fire(&mut self, trigger: Option<usize>, mut lanes: Cell) -> Cell {
+        let sequence = match trigger {
+            Some(index) => self.triggers[index].as_mut().unwrap(),
+            None => &mut self.main,
+        };
+        let len = sequence.groups.len() as u32;
+        let mut ended = 0;
+        while lanes != 0 {
+            // lanes sharing the head of the first one play the same group
+            let head = sequence.head(lanes.trailing_zeros() as usize);
+            let group_lanes = sequence.at(head, lanes);
+            lanes &= !group_lanes;
+            let next = if head == len {
+                if trigger.is_none() && self.show_end == ShowEnd::Stop {
+                    ended |= group_lanes;
+                    continue;
+                }
+                u32::from(len != 0)
+            } else {
+                head + 1
+            };
+            sequence.set(next, group_lanes);
+            let Some(group) = next
+                .checked_sub(1)
+                .map(|group| &sequence.groups[group as usize])
+            else {
+                continue;
+            };
+            for &shape in &group.shows {
+                self.visible[shape] |= group_lanes;
+            }
+            for &shape in &group.hides {
+                self.visible[shape] &= !group_lanes;
+            }
+            for &(shape, place) in &group.moves {
+                for at in &mut self.at[shape] {
+                    *at &= !group_lanes;
+                }
+                self.at[shape][place as usize] |= group_lanes;
+            }
+        }
+        ended
+    }
+
+    // Clicks the point on the lanes, returns those finding the show over
+    // like the minimal runner does on Outcome::EndOfShow
+    pub fn click(&mut self, x: f32, y: f32, lanes: Cell) -> Cell {
+        if !in_bounds(x, y, self.width, self.height) {
+            return 0;
+        }
+        let (hits, missed) = self.under(x, y, lanes);
+        let mut main = missed;
+        let mut ended = 0;
+        for (shape, hit) in hits {
+            if self.triggers[shape].is_some() {
+                ended |= self.fire(Some(shape), hit);
+            } else {
+                main |= hit;
+            }
+        }
+        ended | self.fire(None, main)
+    }
+
+    fn place(&self, lane: usize, index: usize) -> usize {
+        self.at[index]
+            .iter()
+            .position(|at| at & (1 << lane) != 0)
+            .unwrap_or(0)
+    }
+
+    pub fn shape(&self, lane: usize, index: usize) -> ShapeView {
+        let (x, y) = self.lattice[index][self.place(lane, index)];
+        let (w, h) = self.sizes[index];
+        ShapeView {
+            visible: self.visible[index] & (1 << lane) != 0,
+            x,
+            y,
+            w,
+            h,
+            color: self.colors[index],
+        }
+    }
+
+    pub fn render(&self, lane: usize, scale: f32, background: Color) -> Canvas<Color> {
+        let width = (self.width * scale) as usize;
+        let height = (self.height * scale) as usize;
+        let mut canvas = Canvas::new(width, height, background);
+        for i in 0..self.visible.len() {
+            let shape = self.shape(lane, i);
+            if shape.visible {
+                let x = (shape.x * scale + 0.5) as isize;
+                let y = (shape.y * scale + 0.5) as isize;
+                let w = (shape.w * scale + 0.5) as isize;
+                let h = (shape.h * scale + 0.5) as isize;
+                canvas.fill_rect(x, y, w, h, shape.color);
+            }
+        }
+        canvas
+    }
+
+    pub fn len(&self) -> usize {
+        self.visible.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.visible.is_empty()
+    }
+}
+
+// One lane of the sliced runner seen as a runner of its own, clicks only
+// reaching that lane, so that diff::compare checks it against the others
+#[derive(Clone)]
+pub struct Lane {
+    pub sliced: Presentation,
+    pub lane: usize,
+}
+
+impl Lane {
+    fn sequence(&self, trigger: Option<usize>) -> &Sequence {
+        match trigger {
+            Some(index) => self.sliced.triggers[index].as_ref().unwrap(),
+            None => &self.sliced.main,
+        }
+    }
+    fn heads(&self) -> Vec<u32> {
+        let triggers = self.sliced.triggers.iter();
+        let heads = triggers.map(|sequence| sequence.as_ref().map_or(0, |s| s.head(self.lane)));
+        heads.chain([self.sliced.main.head(self.lane)]).collect()
+    }
+}
+
+impl Runner for Lane {
+    const KIND: RunnerKind = RunnerKind::Minimal;
+    type Snapshot = (Vec<bool>, Vec<usize>, Vec<u32>);
+
+    fn build(slide: Slide, config: Config) -> Result<Self> {
+        Ok(Self {
+            sliced: Presentation::build(slide, config)?,
+            lane: 0,
+        })
+    }
+    fn click(&mut self, x: f32, y: f32) -> Result<Outcome> {
+        if !in_bounds(x, y, self.sliced.width, self.sliced.height) {
+            return Ok(Outcome::Missed);
+        }
+        // the click counts its own hit test
+        let iters = self.sliced.iters;
+        let trigger = self
+            .under(x, y)
+            .filter(|index| self.sliced.triggers[*index].is_some());
+        self.sliced.iters = iters;
+        if self.sliced.click(x, y, 1 << self.lane) != 0 {
+            return Ok(Outcome::EndOfShow);
+        }
+        let sequence = self.sequence(trigger);
+        let head = sequence.head(self.lane) as usize;
+        let animations = head
+            .checked_sub(1)
+            .map_or(0, |g| sequence.groups[g].animations);
+        Ok(Outcome::Fired {
+            trigger: Trigger::from(trigger),
+            animations,
+        })
+    }
+    fn under(&mut self, x: f32, y: f32) -> Option<usize> {
+        let (hits, _) = self.sliced.under(x, y, 1 << self.lane);
+        hits.first().map(|(index, _)| *index)
+    }
+    fn render(&self, scale: f32, background: Color) -> Canvas<Color> {
+        self.sliced.render(self.lane, scale, background)
+    }
+    fn iters(&self) -> usize {
+        self.sliced.iters
+    }
+    fn set_iters(&mut self, iters: usize) {
+        self.sliced.iters = iters;
+    }
+    fn len(&self) -> usize {
+        self.sliced.len()
+    }
+    fn shape(&self, index: usize) -> ShapeView {
+        self.sliced.shape(self.lane, index)
+    }
+    fn name(&self, index: usize) -> &'static str {
+        self.sliced.names[index]
+    }
+    fn state_hash(&self) -> u64 {
+        let (visible, places, heads) = self.snapshot();
+        let shapes = visible.iter().zip(&places).enumerate();
+        let heads = heads.iter().enumerate();
+        shapes.fold(0, |hash, (i, (visible, place))| {
+            hash ^ hash::key(hash::VISIBLE, i, *visible as u64)
+                ^ hash::key(hash::PLACE, i, *place as u64)
+        }) ^ heads.fold(0, |hash, (i, head)| {
+            hash ^ hash::key(hash::HEAD, i, *head as u64)
+        })
+    }
+    fn snapshot(&self) -> Self::Snapshot {
+        let lane = self.lane;
+        let visible = self.sliced.visible.iter();
+        let visible = visible.map(|visible| visible & (1 << lane) != 0).collect();
+        let places = (0..self.len())
+            .map(|i| self.sliced.place(lane, i))
+            .collect();
+        (visible, places, self.heads())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        diff,
+        experiments::{self, rule110_input, RULE110_CLOCK, RULE110_SETUP},
+        runners::Config,
+    };
+
+    type Minimal = minimal::Presentation;
+
+    // Every lane plays rule110 from its own input like a minimal runner would
+    #[test]
+    fn rule110() {
+        let config = Config {
+            target: RULE110_CLOCK,
+            ..Config::default()
+        };
+        let (x, y) = RULE110_CLOCK;
+        let mut sliced = Presentation::build(experiments::rule110(), config).unwrap();
+        for (x, y) in RULE110_SETUP {
+            sliced.click(x, y, ALL);
+        }
+        for lane in 0..LANES {
+            for (x, y) in rule110_input(lane) {
+                sliced.click(x, y, 1 << lane);
+            }
+        }
+        for _ in 0..500 {
+            sliced.click(x, y, ALL);
+        }
+        // a lane on its own plays the whole script like the minimal runner
+        let mut script = RULE110_SETUP.to_vec();
+        script.extend(rule110_input(LANES - 1));
+        script.extend([RULE110_CLOCK; 200]);
+        let divergence = diff::compare::<Lane, Minimal>(&experiments::rule110(), config, &script);
+        assert!(matches!(divergence, Ok(None)), "{divergence:?}");
+        for lane in 0..LANES {
+            let mut minimal = Minimal::build(experiments::rule110(), config).unwrap();
+            for (x, y) in RULE110_SETUP.into_iter().chain(rule110_input(lane)) {
+                minimal.click(x, y).unwrap();
+            }
+            for _ in 0..500 {
+                minimal.click(x, y).unwrap();
+            }
+            for index in 0..minimal.len() {
+                let (l, r) = (minimal.shape(index), sliced.shape(lane, index));
+                assert_eq!(l.visible, r.visible, "lane {lane} shape {index}");
+                if l.visible {
+                    assert_eq!(l, r, "lane {lane} shape {index}");
+                }
+            }
+        }
+    }
+}
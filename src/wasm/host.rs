@@ -22,3 +22,10 @@ macro_rules! console {
         $crate::host::pptrs::log(&format_args!($($t)*).to_string());
     };
 }
+
+#[macro_export]
+macro_rules! console_error {
+    ($($t:tt)*) => {
+        $crate::host::pptrs::error(&format_args!($($t)*).to_string());
+    };
+}
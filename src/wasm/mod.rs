@@ -1,14 +1,32 @@
 #![no_main]
 
-use powerpointrs::{experiments, runners::basic::Presentation, Color};
+use powerpointrs::{
+    experiments,
+    runners::{basic, Config, Outcome, Runner},
+    Color, Slide,
+};
 mod host;
 
+// The runner driven by the page, the vertex buffers below expose the state of
+// the basic one to WebGL
+type Presentation = basic::Presentation;
+
 #[no_mangle]
-pub extern "C" fn main() -> Box<Presentation> {
+pub extern "C" fn main() -> Option<Box<Presentation>> {
     // let slide = experiments::test();
     // let slide = experiments::sort();
     let slide = experiments::rule110();
-    Box::new(Presentation::from(slide))
+    build(slide).map(Box::new)
+}
+
+fn build<R: Runner>(slide: Slide) -> Option<R> {
+    match R::build(slide, Config::default()) {
+        Ok(presentation) => Some(presentation),
+        Err(error) => {
+            console_error!("{error}");
+            None
+        }
+    }
 }
 
 #[no_mangle]
@@ -17,14 +35,30 @@ pub extern "C" fn display(presentation: &Presentation) {
 }
 #[no_mangle]
 pub extern "C" fn print(presentation: &Presentation, scale: f32) {
-    console!("{}", presentation.render(scale, Color::WHITE));
+    console!("{}", Runner::render(presentation, scale, Color::WHITE));
 }
 
 #[no_mangle]
-pub extern "C" fn click(presentation: &mut Presentation, x: f32, y: f32, n: usize) {
-    for _ in 0..n {
-        presentation.click(x, y);
+pub extern "C" fn click(presentation: &mut Presentation, x: f32, y: f32, n: usize) -> usize {
+    clicks(presentation, x, y, n)
+}
+
+// Clicks n times at the point, returning how many clicks were played
+fn clicks<R: Runner>(presentation: &mut R, x: f32, y: f32, n: usize) -> usize {
+    for i in 0..n {
+        match presentation.click(x, y) {
+            Ok(Outcome::EndOfShow) => {
+                console!("end of show");
+                return i;
+            }
+            Ok(_) => {}
+            Err(error) => {
+                console_error!("{error}");
+                return i;
+            }
+        }
     }
+    n
 }
 
 const DYNAMIC: usize = 0;
@@ -33,30 +67,30 @@ const CONSTANT: usize = 1;
 #[no_mangle]
 pub extern "C" fn get_vbo_ptr(presentation: &mut Presentation, index: usize) -> *mut u8 {
     match index {
-        DYNAMIC => presentation.states_dyn.as_mut_ptr() as _,
-        CONSTANT => presentation.states_const.as_mut_ptr() as _,
+        DYNAMIC => presentation.state.states_dyn.as_mut_ptr() as _,
+        CONSTANT => presentation.program.states_const.as_ptr() as _,
         _ => 0 as _,
     }
 }
 #[no_mangle]
 pub extern "C" fn get_vbo_size(presentation: &mut Presentation, index: usize) -> usize {
     match index {
-        DYNAMIC => presentation.states_dyn.len(),
-        CONSTANT => presentation.states_const.len(),
+        DYNAMIC => presentation.state.states_dyn.len(),
+        CONSTANT => presentation.program.states_const.len(),
         _ => 0,
     }
 }
 
 #[no_mangle]
 pub extern "C" fn get_subdata_slice(presentation: &mut Presentation) -> *const u8 {
-    &presentation.cache_data as *const _ as _
+    &presentation.state.cache_data as *const _ as _
 }
 
 #[no_mangle]
 pub extern "C" fn get_width(presentation: &mut Presentation) -> f32 {
-    presentation.width
+    presentation.program.width
 }
 #[no_mangle]
 pub extern "C" fn get_height(presentation: &mut Presentation) -> f32 {
-    presentation.height
+    presentation.program.height
 }
@@ -0,0 +1,549 @@
+use std::sync::Arc;
+
+use powerpointrs::{
+    diff,
+    experiments::{self, shuttle, RULE110_CLOCK, RULE110_SETUP},
+    runners::{
+        basic::Presentation as Basic,
+        batch::{self, Plan},
+        memo, minimal,
+        profile::Stats,
+        reference::Presentation as Reference,
+        sliced, CompileReport, Config, Cycle, Halt, HitTest, Outcome, Run, Runner, ShowEnd,
+        Trigger,
+    },
+    shape, z, Color, Error, MacroEffect, Slide,
+};
+
+type Minimal = minimal::Presentation;
+type Memo = memo::Presentation;
+
+fn config(target: (f32, f32)) -> Config {
+    Config {
+        target,
+        ..Config::default()
+    }
+}
+
+#[test]
+fn sort() {
+    let script = vec![(1., 11.); 2000];
+    let divergence =
+        diff::compare::<Basic, Minimal>(&experiments::sort(), config((0., 10.)), &script);
+    assert!(matches!(divergence, Ok(None)), "{divergence:?}");
+}
+
+// A shape flying out is parked at home by every runner, flying it back in
+// or making it appear shows it there
+#[test]
+fn slide_out() {
+    let mut slide = Slide::new(40., 30.);
+    let shape = slide.rect(10., 10., 4., 4.).handle();
+    slide
+        .main()
+        .animate(shape, MacroEffect::SlideOut)
+        .after_click()
+        .animate(shape, MacroEffect::Appear)
+        .after_click();
+    let script = [(30., 20.), (30., 20.)];
+    let divergence = diff::compare::<Basic, Minimal>(&slide, config((0., 0.)), &script);
+    assert!(matches!(divergence, Ok(None)), "{divergence:?}");
+    let divergence = diff::compare::<Reference, Basic>(&slide, config((0., 0.)), &script);
+    assert!(matches!(divergence, Ok(None)), "{divergence:?}");
+    let mut basic = Basic::build(slide, config((0., 0.))).unwrap();
+    for (x, y) in script {
+        basic.click(x, y).unwrap();
+    }
+    assert_eq!(basic.under(11., 11.), Some(0));
+}
+
+#[test]
+fn rule110() {
+    let mut script = RULE110_SETUP.to_vec();
+    script.extend(experiments::rule110_input(1));
+    script.extend([RULE110_CLOCK; 500]);
+    let divergence =
+        diff::compare::<Basic, Minimal>(&experiments::rule110(), config(RULE110_CLOCK), &script);
+    assert!(matches!(divergence, Ok(None)), "{divergence:?}");
+}
+
+// Input clicks along the rule110 row between the steps, off the hot point
+#[test]
+fn rule110_input() {
+    let mut script = vec![(15., 15.), (22., 4.), (34., 4.), (42., 4.)];
+    for step in 0..40 {
+        script.push((60. + step as f32, 10.));
+        script.extend([RULE110_CLOCK; 10]);
+    }
+    for hit_test in [HitTest::Grid, HitTest::Partition] {
+        let config = Config {
+            hit_test,
+            ..config(RULE110_CLOCK)
+        };
+        let divergence = diff::compare::<Basic, Minimal>(&experiments::rule110(), config, &script);
+        assert!(
+            matches!(divergence, Ok(None)),
+            "{hit_test:?} {divergence:?}"
+        );
+    }
+}
+
+// Both runners reach the STOP shape of rule110 after the same number of clicks
+#[test]
+fn rule110_run() {
+    let config = Config {
+        hit_test: HitTest::Filter,
+        ..config(RULE110_CLOCK)
+    };
+    let mut basic = Basic::build(experiments::rule110(), config).unwrap();
+    let run = basic
+        .run(
+            RULE110_CLOCK,
+            &[Halt::Topmost("STOP"), Halt::EndOfShow],
+            100_000,
+        )
+        .unwrap();
+    assert_eq!(run.halt, Some(0));
+    let mut minimal = Minimal::build(experiments::rule110(), config).unwrap();
+    let halts = [Halt::Topmost("STOP"), Halt::EndOfShow];
+    assert_eq!(minimal.run(RULE110_CLOCK, &halts, 100_000), Ok(run));
+
+    // checking for the STOP shape leaves the iterations to the clicks
+    let config = Config {
+        hit_test: HitTest::Grid,
+        ..config
+    };
+    let mut basic = Basic::build(experiments::rule110(), config).unwrap();
+    let halts = [Halt::Topmost("STOP"), Halt::EndOfShow];
+    assert_eq!(basic.run(RULE110_CLOCK, &halts, 100_000), Ok(run));
+    let mut clicked = Basic::build(experiments::rule110(), config).unwrap();
+    for _ in 0..run.clicks {
+        clicked.click(RULE110_CLOCK.0, RULE110_CLOCK.1).unwrap();
+    }
+    assert_eq!(basic.iters(), clicked.iters());
+
+    // the predicate never holds, the run gives up at the limit
+    let mut minimal = Minimal::build(experiments::rule110(), config).unwrap();
+    let halts = [Halt::Predicate(Box::new(|minimal: &Minimal| {
+        minimal.is_empty()
+    }))];
+    assert_eq!(
+        minimal.run(RULE110_CLOCK, &halts, 10),
+        Ok(Run {
+            clicks: 10,
+            halt: None,
+            cycle: None
+        })
+    );
+}
+
+// Incremental hashes stay equal to a full rehash along rule110
+#[test]
+fn rule110_hash() {
+    let mut basic = Basic::build(experiments::rule110(), config(RULE110_CLOCK)).unwrap();
+    let mut minimal = Minimal::build(experiments::rule110(), config(RULE110_CLOCK)).unwrap();
+    for click in 0..300 {
+        let (x, y) = if click % 50 == 7 {
+            (60., 10.)
+        } else {
+            RULE110_CLOCK
+        };
+        basic.click(x, y).unwrap();
+        minimal.click(x, y).unwrap();
+        assert_eq!(basic.state.hash, basic.rehash(), "click {click}");
+        assert_eq!(minimal.state.hash, minimal.rehash(), "click {click}");
+    }
+}
+
+// A wrapping main sequence settles into a loop once the shape left home
+#[test]
+fn cycle() {
+    let mut slide = Slide::new(40., 30.);
+    let shape = slide.rect(2., 2., 4., 4.).handle();
+    slide
+        .main()
+        .animate(shape, MacroEffect::Disappear)
+        .animate(shape, MacroEffect::Appear)
+        .after_click()
+        .animate(shape, MacroEffect::Path(5., 0.))
+        .after_click();
+    let config = Config {
+        show_end: ShowEnd::Wrap,
+        ..config((0., 0.))
+    };
+    let expected = Ok(Run {
+        clicks: 6,
+        halt: Some(0),
+        cycle: Some(Cycle {
+            start: 3,
+            length: 3,
+        }),
+    });
+    let mut basic = Basic::build(slide.clone(), config).unwrap();
+    assert_eq!(basic.run((30., 20.), &[Halt::Cycle], 100), expected);
+    let mut minimal = Minimal::build(slide.clone(), config).unwrap();
+    assert_eq!(minimal.run((30., 20.), &[Halt::Cycle], 100), expected);
+    // the memo runner looks for cycles click by click
+    let mut memo = Memo::build(slide, config).unwrap();
+    assert_eq!(memo.run((30., 20.), &[Halt::Cycle], 100), expected);
+}
+
+// A sweep over initial rows gives the reports of the runs played one by one,
+// in the order of the scripts
+#[test]
+fn batch() {
+    let scripts = (1..6)
+        .map(|row: usize| {
+            let mut script = RULE110_SETUP.to_vec();
+            script.extend(experiments::rule110_input(row));
+            script
+        })
+        .collect::<Vec<_>>();
+    let plan = Plan {
+        point: RULE110_CLOCK,
+        halts: vec![Halt::Topmost("STOP"), Halt::EndOfShow],
+        limit: 100_000,
+        scale: 1.,
+        background: Color::WHITE,
+    };
+    let minimal = Minimal::build(experiments::rule110(), config(RULE110_CLOCK)).unwrap();
+    let reports = batch::run(&minimal, &scripts, &plan, 3);
+    assert_eq!(reports.len(), scripts.len());
+    for (script, report) in scripts.iter().zip(reports) {
+        let mut alone = minimal.clone();
+        for &(x, y) in script {
+            alone.click(x, y).unwrap();
+        }
+        let run = alone.run(plan.point, &plan.halts, plan.limit).unwrap();
+        let clicks = script.len() + run.clicks;
+        assert_eq!(report.result, Ok(Run { clicks, ..run }));
+        assert_eq!(report.render.pixels, alone.render(1., Color::WHITE).pixels);
+    }
+}
+
+// The memo runner plays the clicks of the minimal one, and its runs to the
+// STOP shape of rule110 skipping through segments end in the same states
+#[test]
+fn memo() {
+    let mut script = RULE110_SETUP.to_vec();
+    script.extend(experiments::rule110_input(3));
+    script.extend([RULE110_CLOCK; 200]);
+    let divergence =
+        diff::compare::<Minimal, Memo>(&experiments::rule110(), config(RULE110_CLOCK), &script);
+    assert!(matches!(divergence, Ok(None)), "{divergence:?}");
+
+    let scripts = (1..4)
+        .map(|row: usize| {
+            let mut script = RULE110_SETUP.to_vec();
+            script.extend(experiments::rule110_input(row));
+            script
+        })
+        .collect::<Vec<_>>();
+    fn plan<R>() -> Plan<R> {
+        Plan {
+            point: RULE110_CLOCK,
+            halts: vec![Halt::Topmost("STOP"), Halt::EndOfShow],
+            limit: 100_000,
+            scale: 1.,
+            background: Color::WHITE,
+        }
+    }
+    let minimal = Minimal::build(experiments::rule110(), config(RULE110_CLOCK)).unwrap();
+    let memo = Memo::build(experiments::rule110(), config(RULE110_CLOCK)).unwrap();
+    let minimals = batch::run(&minimal, &scripts, &plan(), 3);
+    let memos = batch::run(&memo, &scripts, &plan(), 3);
+    for (minimal, memo) in minimals.iter().zip(&memos) {
+        assert!(matches!(minimal.result, Ok(Run { halt: Some(0), .. })));
+        assert_eq!(minimal.result, memo.result);
+        assert_eq!(minimal.render.pixels, memo.render.pixels);
+    }
+}
+
+// Whatever word the minimal runner packs its cells in, it plays the same
+// shows and hashes its states the same, heads included
+#[test]
+fn words() {
+    let config = Config {
+        hit_test: HitTest::Partition,
+        ..config(RULE110_CLOCK)
+    };
+    let mut native = Minimal::build(experiments::rule110(), config).unwrap();
+    let mut narrow = minimal::Presentation::<u32>::build(experiments::rule110(), config).unwrap();
+    let mut wide = minimal::Presentation::<u128>::build(experiments::rule110(), config).unwrap();
+    for click in 0..1000 {
+        let (x, y) = if click % 50 == 7 {
+            (60., 10.)
+        } else {
+            RULE110_CLOCK
+        };
+        let outcome = native.click(x, y).unwrap();
+        assert_eq!(narrow.click(x, y).unwrap(), outcome, "click {click}");
+        assert_eq!(wide.click(x, y).unwrap(), outcome, "click {click}");
+        assert_eq!(narrow.state_hash(), native.state_hash(), "click {click}");
+        assert_eq!(wide.state_hash(), native.state_hash(), "click {click}");
+    }
+    assert_eq!(narrow.shapes(), native.shapes());
+    assert_eq!(wide.shapes(), native.shapes());
+    assert_eq!(wide.rehash(), wide.state.hash);
+}
+
+// Both runners report the same slide, the minimal one merging animations by
+// cell into fewer and smaller ones
+#[test]
+fn compile_report() {
+    let basic = Basic::build(experiments::rule110(), config(RULE110_CLOCK)).unwrap();
+    let minimal = Minimal::build(experiments::rule110(), config(RULE110_CLOCK)).unwrap();
+    let (basic, minimal) = (&basic.program.report, &minimal.program.report);
+    assert_eq!((basic.shapes, basic.flattened), (10841, 10841));
+    assert_eq!(basic.animations, basic.merged);
+    assert_eq!(
+        CompileReport {
+            merged: basic.merged,
+            memory: basic.memory,
+            ..minimal.clone()
+        },
+        *basic
+    );
+    assert_eq!(basic.groups.len(), basic.flattened + 1);
+    assert!(minimal.merged < minimal.animations);
+    assert!(minimal.memory < basic.memory);
+}
+
+// Both runners profile the same triggers firing, with the animations of the
+// slide they played
+#[test]
+fn profile() {
+    let profiled = Config {
+        profile: true,
+        ..config(RULE110_CLOCK)
+    };
+    let mut basic = Basic::build(experiments::rule110(), profiled).unwrap();
+    let mut minimal = Minimal::build(experiments::rule110(), profiled).unwrap();
+    let mut script = vec![(15., 15.), (22., 4.), (26., 4.), (120., 10.)];
+    script.extend([RULE110_CLOCK; 500]);
+    for &(x, y) in &script {
+        basic.click(x, y).unwrap();
+        minimal.click(x, y).unwrap();
+    }
+    let (profile, other) = (basic.profile().unwrap(), minimal.profile().unwrap());
+    let fired = |stats: &Stats| (stats.fired, stats.animations, stats.groups.len());
+    let counts = profile.contexts.iter().map(fired).collect::<Vec<_>>();
+    assert_eq!(other.contexts.iter().map(fired).collect::<Vec<_>>(), counts);
+    let clicks = profile
+        .contexts
+        .iter()
+        .map(|stats| stats.fired)
+        .sum::<usize>();
+    assert_eq!(clicks, script.len());
+
+    let hottest = minimal.hottest(20);
+    assert_eq!(hottest.len(), 20);
+    assert!(hottest
+        .windows(2)
+        .all(|w| w[0].stats.fired >= w[1].stats.fired));
+    assert_eq!(hottest[0].name, "ZERO");
+    assert!(
+        Minimal::build(experiments::rule110(), config(RULE110_CLOCK))
+            .unwrap()
+            .profile()
+            .is_none()
+    );
+}
+
+// Machines share the program they are cloned from, and a state saved along
+// the way plays the same clicks again once restored
+#[test]
+fn snapshot() {
+    let mut basic = Basic::build(experiments::rule110(), config(RULE110_CLOCK)).unwrap();
+    let mut minimal = Minimal::build(experiments::rule110(), config(RULE110_CLOCK)).unwrap();
+    for (x, y) in [(15., 15.), (22., 4.), (26., 4.), (120., 10.)] {
+        basic.click(x, y).unwrap();
+        minimal.click(x, y).unwrap();
+    }
+    let (basic_fork, minimal_fork) = (basic.clone(), minimal.clone());
+    assert!(Arc::ptr_eq(&basic.program, &basic_fork.program));
+    assert!(Arc::ptr_eq(&minimal.program, &minimal_fork.program));
+    let (x, y) = RULE110_CLOCK;
+    for _ in 0..500 {
+        basic.click(x, y).unwrap();
+        minimal.click(x, y).unwrap();
+    }
+    let expected = (basic.shapes(), basic.state_hash(), minimal.state_hash());
+    basic.state = basic_fork.state;
+    minimal.state = minimal_fork.state;
+    for _ in 0..500 {
+        basic.click(x, y).unwrap();
+        minimal.click(x, y).unwrap();
+    }
+    let shapes = (basic.shapes(), basic.state_hash(), minimal.state_hash());
+    assert!(shapes == expected);
+}
+
+// Every click of the shuttle should take the fast path
+#[test]
+fn hot_points() {
+    let slide = shuttle();
+    let script = [(0., 0.), (20., 0.), (0., 0.), (0., 0.)].repeat(10);
+    let divergence = diff::compare::<Basic, Minimal>(&slide, config((0., 0.)), &script);
+    assert!(matches!(divergence, Ok(None)), "{divergence:?}");
+
+    let mut minimal = Minimal::build(slide, config((0., 0.))).unwrap();
+    for &(x, y) in &script {
+        minimal.click(x, y).unwrap();
+    }
+    // no click scans more than the filter cells
+    let cells = minimal.state.visible.cells();
+    assert!((1..=script.len() * cells).contains(&minimal.iters()));
+}
+
+// The shuttle has no main sequence, clicking the background ends the show
+// right away or plays nothing when wrapping around
+#[test]
+fn empty_main_sequence() {
+    let script = [(30., 20.), (0., 0.), (30., 20.), (20., 0.), (30., 20.)];
+    for show_end in [ShowEnd::Stop, ShowEnd::Wrap] {
+        let config = Config {
+            show_end,
+            ..config((0., 0.))
+        };
+        let divergence = diff::compare::<Basic, Minimal>(&shuttle(), config, &script);
+        assert!(matches!(divergence, Ok(None)), "{divergence:?}");
+        let divergence = diff::compare::<sliced::Lane, Minimal>(&shuttle(), config, &script);
+        assert!(matches!(divergence, Ok(None)), "{divergence:?}");
+        // the reference runner accumulates the relative paths of the
+        // shuttle, it only plays the background clicks
+        let background = [(30., 20.); 3];
+        let divergence = diff::compare::<Reference, Basic>(&shuttle(), config, &background);
+        assert!(matches!(divergence, Ok(None)), "{divergence:?}");
+        let mut minimal = Minimal::build(shuttle(), config).unwrap();
+        let outcome = minimal.click(30., 20.);
+        match show_end {
+            ShowEnd::Stop => assert_eq!(outcome, Ok(Outcome::EndOfShow)),
+            ShowEnd::Wrap => assert_eq!(
+                outcome,
+                Ok(Outcome::Fired {
+                    trigger: Trigger::Main,
+                    animations: 0
+                })
+            ),
+        }
+    }
+}
+
+mod generated {
+    include!("generated/shuttle.rs");
+}
+
+// The module codegen made of the shuttle plays it like the minimal runner
+#[test]
+fn codegen() {
+    let mut script = [(0., 0.), (20., 0.), (0., 0.), (0., 0.), (12., 12.)].repeat(10);
+    script.extend([(30., 20.), (30., 20.)]);
+    let divergence =
+        diff::compare::<Minimal, generated::Presentation>(&shuttle(), config((0., 0.)), &script);
+    assert!(matches!(divergence, Ok(None)), "{divergence:?}");
+
+    let mut minimal = Minimal::build(shuttle(), config((0., 0.))).unwrap();
+    let mut generated = generated::Presentation::build(shuttle(), config((0., 0.))).unwrap();
+    for (x, y) in script {
+        assert_eq!(minimal.click(x, y), generated.click(x, y));
+        assert_eq!(minimal.state_hash(), generated.state_hash());
+    }
+    assert_eq!(minimal.iters(), generated.iters());
+
+    // it refuses any other slide
+    let mut moved = shuttle();
+    moved.shapes[0].1 = shape!(1, 0, 4, 4);
+    let built = generated::Presentation::build(moved, config((0., 0.)));
+    assert!(matches!(built, Err(Error::SlideMismatch { .. })));
+}
+
+// A shape parked at several places away from any hot point
+#[test]
+fn lattice() {
+    let mut slide = Slide::new(40., 30.);
+    let shape = slide.rect(2., 2., 4., 4.).z(z!(1)).handle();
+    slide
+        .on(shape)
+        .animate(shape, MacroEffect::Target(20., 2.))
+        .animate(shape, MacroEffect::Path(0., 20.))
+        .after_click()
+        .animate(shape, MacroEffect::Target(30., 10.))
+        .after_click();
+    let mut script = vec![(3., 3.)];
+    script.extend([(21., 3.), (3., 23.), (31., 11.)].repeat(5));
+    let divergence = diff::compare::<Basic, Minimal>(&slide, config((0., 0.)), &script);
+    assert!(matches!(divergence, Ok(None)), "{divergence:?}");
+}
+
+#[test]
+fn test() {
+    let divergence = diff::compare::<Basic, Minimal>(&experiments::test(), config((0., 0.)), &[]);
+    assert!(matches!(divergence, Err(Error::UnsupportedShape { .. })));
+}
+
+#[test]
+fn reference_sort() {
+    let script = vec![(1., 11.); 2000];
+    let divergence =
+        diff::compare::<Reference, Basic>(&experiments::sort(), config((0., 10.)), &script);
+    assert!(matches!(divergence, Ok(None)), "{divergence:?}");
+}
+
+#[test]
+fn reference_test() {
+    let script = [
+        (1.5, 1.5),
+        (2.5, 2.5),
+        (3.5, 3.5),
+        (3.5, 3.5),
+        (3.5, 3.5),
+        (1., 1.),
+        (5.5, 5.5),
+        (1.5, 1.5),
+        (1.5, 1.5),
+    ];
+    let divergence =
+        diff::compare::<Reference, Basic>(&experiments::test(), config((0., 0.)), &script);
+    assert!(matches!(divergence, Ok(None)), "{divergence:?}");
+}
+
+// The topmost shape under a point is the one drawn there, even when it is not
+// the first shape of its group
+#[test]
+fn reference_under() {
+    // shows the first shape, which shows the second, which shows the group
+    let script = [(1.5, 1.5), (1.5, 1.5), (2.5, 2.5), (0., 0.)];
+    let mut basic = Basic::build(experiments::test(), config((0., 0.))).unwrap();
+    let mut reference = Reference::build(experiments::test(), config((0., 0.))).unwrap();
+    let mut inner = 0;
+    for (x, y) in script {
+        for point in (0..40).map(|i| (i as f32 / 2., i as f32 / 2.)) {
+            let index = Runner::under(&mut reference, point.0, point.1);
+            assert_eq!(Runner::under(&mut basic, point.0, point.1), index);
+            if let Some(index) = index {
+                let shape = reference.shape(index);
+                assert!(shape.visible && shape.x <= point.0 && point.0 <= shape.x + shape.w);
+                inner += usize::from(reference.owners[index] != index);
+            }
+        }
+        basic.click(x, y).unwrap();
+        reference.click(x, y).unwrap();
+    }
+    assert!(inner > 0);
+}
+
+#[test]
+fn reference_rule110() {
+    let mut script = RULE110_SETUP.to_vec();
+    script.extend(experiments::rule110_input(1));
+    script.extend([RULE110_CLOCK; 100]);
+    let divergence =
+        diff::compare::<Reference, Basic>(&experiments::rule110(), config(RULE110_CLOCK), &script)
+            .unwrap()
+            .expect("runners agree on rule110");
+    // Place is a null motion path for the reference runner, the basic runner
+    // uses it to send cells back home
+    assert_eq!(divergence.click, 9);
+    assert_eq!(divergence.shape, Some((42, "")));
+}
@@ -0,0 +1,254 @@
+// Generated by powerpointrs::codegen, do not edit
+
+use ::powerpointrs::{
+    codegen, hash, in_bounds,
+    render::Canvas,
+    runners::{Config, Outcome, Runner, RunnerKind, ShapeView, ShowEnd, Trigger},
+    Color, Error, Result, Slide,
+};
+
+type Cell = u64;
+const CELL_SIZE: usize = 64;
+// codegen::fingerprint of the slide compiled in
+const SLIDE: u64 = 0x27f7146cf92acd1;
+
+pub const LEN: usize = 2;
+const WIDTH: f32 = 40.0;
+const HEIGHT: f32 = 30.0;
+static SIZES: [(f32, f32); LEN] = [
+    (4.0, 4.0),
+    (4.0, 4.0),
+];
+static COLORS: [Color; LEN] = [
+    Color::new(0, 0, 0),
+    Color::new(0, 0, 0),
+];
+static NAMES: [&str; LEN] = [
+    "",
+    "",
+];
+static LATTICE: [&[(f32, f32)]; LEN] = [
+    &[(10.0, 10.0), (20.0, 0.0), (0.0, 0.0)],
+    &[(0.0, 0.0)],
+];
+static TARGETS: [(f32, f32); 2] = [
+    (0.0, 0.0),
+    (20.0, 0.0),
+];
+// click groups of each shape context, then of the main one
+static GROUPS: [usize; LEN + 1] = [
+    2,
+    1,
+    0,
+];
+
+#[derive(Clone)]
+pub struct Presentation {
+    visible: Vec<Cell>,
+    planes: Vec<Vec<Cell>>,
+    places: Vec<u32>,
+    heads: Vec<usize>,
+    iters: usize,
+    show_end: ShowEnd,
+}
+
+impl Default for Presentation {
+    fn default() -> Self {
+        Self {
+            visible: vec![0x3],
+            planes: vec![vec![0x2], vec![0x0]],
+            places: vec![0; LEN],
+            heads: vec![0; LEN + 1],
+            iters: 0,
+            show_end: ShowEnd::default(),
+        }
+    }
+}
+
+fn covers(index: usize, place: u32, x: f32, y: f32) -> bool {
+    let (sx, sy) = LATTICE[index][place as usize];
+    let (w, h) = SIZES[index];
+    x >= sx && y >= sy && x <= sx + w && y <= sy + h
+}
+
+fn last_and(a: &[Cell], b: &[Cell]) -> Option<usize> {
+    for (index, (a, b)) in a.iter().zip(b).enumerate().rev() {
+        let combined = a & b;
+        if combined != 0 {
+            return Some(index * CELL_SIZE + CELL_SIZE - 1 - combined.leading_zeros() as usize);
+        }
+    }
+    None
+}
+
+fn main_context(_: &mut Presentation, head: usize) -> usize {
+    match head {
+        0 => 0,
+        _ => unreachable!(),
+    }
+}
+
+fn context_0_1(p: &mut Presentation) -> usize {
+    p.planes[0][0] |= 0x1;
+    p.planes[1][0] &= !0x1;
+    p.places[0] = 2;
+    1
+}
+
+fn context_0_2(p: &mut Presentation) -> usize {
+    p.planes[0][0] &= !0x1;
+    p.planes[1][0] &= !0x1;
+    p.places[0] = 0;
+    1
+}
+
+fn context_0(p: &mut Presentation, head: usize) -> usize {
+    match head {
+        1 => context_0_1(p),
+        2 => context_0_2(p),
+        _ => unreachable!(),
+    }
+}
+
+fn context_1_1(p: &mut Presentation) -> usize {
+    p.planes[0][0] &= !0x1;
+    p.planes[1][0] |= 0x1;
+    p.places[0] = 1;
+    1
+}
+
+fn context_1(p: &mut Presentation, head: usize) -> usize {
+    match head {
+        1 => context_1_1(p),
+        _ => unreachable!(),
+    }
+}
+
+impl Presentation {
+    pub fn under(&mut self, x: f32, y: f32) -> Option<usize> {
+        if !in_bounds(x, y, WIDTH, HEIGHT) {
+            return None;
+        }
+        if let Some(plane) = TARGETS.iter().position(|target| *target == (x, y)) {
+            let last = last_and(&self.visible, &self.planes[plane]);
+            let cells = self.visible.len();
+            self.iters += last.map_or(cells, |index| cells - index / CELL_SIZE);
+            return last;
+        }
+        for i in (0..LEN).rev() {
+            if self.visible[i / CELL_SIZE] & (1 << (i % CELL_SIZE)) != 0 && covers(i, self.places[i], x, y) {
+                self.iters += LEN - i;
+                return Some(i);
+            }
+        }
+        self.iters += LEN;
+        None
+    }
+
+    pub fn click(&mut self, x: f32, y: f32) -> Result<Outcome> {
+        if !in_bounds(x, y, WIDTH, HEIGHT) {
+            return Ok(Outcome::Missed);
+        }
+        let trigger = self
+            .under(x, y)
+            .filter(|index| matches!(index, 0 | 1));
+        let index = trigger.unwrap_or(LEN);
+        let head = if self.heads[index] == GROUPS[index] {
+            if trigger.is_none() && self.show_end == ShowEnd::Stop {
+                return Ok(Outcome::EndOfShow);
+            }
+            usize::from(GROUPS[index] != 0)
+        } else {
+            self.heads[index] + 1
+        };
+        self.heads[index] = head;
+        let animations = match trigger {
+            Some(0) => context_0(self, head),
+            Some(1) => context_1(self, head),
+            _ => main_context(self, head),
+        };
+        Ok(Outcome::Fired {
+            trigger: Trigger::from(trigger),
+            animations,
+        })
+    }
+}
+
+// The slide is compiled in, build only checks it was given the same one and
+// reads the show end of the config
+impl Runner for Presentation {
+    const KIND: RunnerKind = RunnerKind::Minimal;
+    type Snapshot = (Vec<Cell>, Vec<u32>, Vec<usize>);
+
+    fn build(slide: Slide, config: Config) -> Result<Self> {
+        let found = codegen::fingerprint(&slide);
+        if found != SLIDE {
+            return Err(Error::SlideMismatch {
+                expected: SLIDE,
+                found,
+            });
+        }
+        Ok(Self {
+            show_end: config.show_end,
+            ..Self::default()
+        })
+    }
+    fn click(&mut self, x: f32, y: f32) -> Result<Outcome> {
+        Presentation::click(self, x, y)
+    }
+    fn under(&mut self, x: f32, y: f32) -> Option<usize> {
+        Presentation::under(self, x, y)
+    }
+    fn render(&self, scale: f32, background: Color) -> Canvas<Color> {
+        let width = (WIDTH * scale) as usize;
+        let height = (HEIGHT * scale) as usize;
+        let mut canvas = Canvas::new(width, height, background);
+        for i in 0..LEN {
+            let shape = self.shape(i);
+            if shape.visible {
+                let x = (shape.x * scale + 0.5) as isize;
+                let y = (shape.y * scale + 0.5) as isize;
+                let w = (shape.w * scale + 0.5) as isize;
+                let h = (shape.h * scale + 0.5) as isize;
+                canvas.fill_rect(x, y, w, h, shape.color);
+            }
+        }
+        canvas
+    }
+    fn iters(&self) -> usize {
+        self.iters
+    }
+    fn set_iters(&mut self, iters: usize) {
+        self.iters = iters;
+    }
+    fn len(&self) -> usize {
+        LEN
+    }
+    fn shape(&self, index: usize) -> ShapeView {
+        let (x, y) = LATTICE[index][self.places[index] as usize];
+        let (w, h) = SIZES[index];
+        ShapeView {
+            visible: self.visible[index / CELL_SIZE] & (1 << (index % CELL_SIZE)) != 0,
+            x,
+            y,
+            w,
+            h,
+            color: COLORS[index],
+        }
+    }
+    fn name(&self, index: usize) -> &'static str {
+        NAMES[index]
+    }
+    // hashed from scratch like the minimal runner does incrementally
+    fn state_hash(&self) -> u64 {
+        let cells = self.visible.iter().enumerate();
+        let places = self.places.iter().enumerate();
+        let heads = self.heads.iter().enumerate();
+        cells.fold(0, |hash, (i, cell)| hash ^ hash::cell(hash::VISIBLE, i, *cell))
+            ^ places.fold(0, |hash, (i, place)| hash ^ hash::key(hash::PLACE, i, *place as u64))
+            ^ heads.fold(0, |hash, (i, head)| hash ^ hash::key(hash::HEAD, i, *head as u64))
+    }
+    fn snapshot(&self) -> Self::Snapshot {
+        (self.visible.clone(), self.places.clone(), self.heads.clone())
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use powerpointrs::{
+    anim,
+    runners::{basic::Presentation as Basic, minimal::Presentation as Minimal, Config, Runner},
+    shape, Slide,
+};
+
+fn visible(runner: &impl Runner) -> Vec<bool> {
+    runner.shapes().iter().map(|shape| shape.visible).collect()
+}
+
+// A shape entering on the first click, and one leaving with it
+fn entrance() -> Slide {
+    let mut slide = Slide::new(40., 30.);
+    let shown = shape!(@slide, 0, 0, 4, 4);
+    let entering = shape!(@slide, 10, 10, 4, 4);
+    anim!(@slide, entering => Appear);
+    anim!(@slide, shown => Disappear);
+    slide
+}
+
+// A shape whose first effect is an entrance starts hidden, in the minimal
+// runner as in the basic one
+#[test]
+fn entrance_starts_hidden() {
+    let mut basic = <Basic as Runner>::build(entrance(), Config::default()).unwrap();
+    let mut minimal = <Minimal as Runner>::build(entrance(), Config::default()).unwrap();
+    assert_eq!(visible(&minimal), [false, true]);
+    assert_eq!(visible(&minimal), visible(&basic));
+
+    basic.click(30., 25.).unwrap();
+    minimal.click(30., 25.).unwrap();
+    assert_eq!(visible(&minimal), [true, false]);
+    assert_eq!(visible(&minimal), visible(&basic));
+}
+
+// A click group showing then hiding a shape and moving another, then one
+// showing the first and hiding the second, both shapes in the same cell
+fn flicker() -> Slide {
+    let mut slide = Slide::new(40., 30.);
+    let flickering = shape!(@slide, 0, 0, 4, 4);
+    let moving = shape!(@slide, 10, 10, 4, 4);
+    anim!(@slide, flickering => Appear, c=true);
+    anim!(@slide, flickering => Disappear);
+    anim!(@slide, moving => Path(5., 5.));
+    anim!(@slide, flickering => Appear, c=true);
+    anim!(@slide, moving => Disappear);
+    slide
+}
+
+// Dense click groups applied through their masks play as they do cell by cell,
+// moves included
+#[test]
+fn dense_masks() {
+    let mut basic = <Basic as Runner>::build(flicker(), Config::default()).unwrap();
+    let mut masked = <Minimal as Runner>::build(flicker(), Config::default()).unwrap();
+    let mut unmasked = <Minimal as Runner>::build(flicker(), Config::default()).unwrap();
+    assert!(masked
+        .program
+        .timeline
+        .main_context
+        .masks
+        .iter()
+        .all(Option::is_some));
+    let program = Arc::get_mut(&mut unmasked.program).unwrap();
+    program.timeline.main_context.masks.fill(None);
+
+    for _ in 0..2 {
+        basic.click(30., 25.).unwrap();
+        masked.click(30., 25.).unwrap();
+        unmasked.click(30., 25.).unwrap();
+        assert_eq!(masked.shapes(), basic.shapes());
+        assert_eq!(unmasked.shapes(), basic.shapes());
+        assert_eq!(masked.state_hash(), unmasked.state_hash());
+    }
+}